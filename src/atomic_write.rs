@@ -0,0 +1,193 @@
+//! Crash-safe replacement of a file: the new content is written to a
+//! temporary file next to it, synced to the disk and renamed over the old
+//! file, so readers see either the old or the new content, never half of it.
+//! The previous contents are kept as `<path>.bak`, `<path>.bak.1`, ...
+//! unless the file is written with `replace`.
+//! The new file keeps the permissions of the old one, new files are only
+//! readable by their owner.
+//!
+//!```
+//!    use rjrn::atomic_write;
+//!    use std::fs;
+//!    use std::path::Path;
+//!
+//!    let dir = std::env::temp_dir().join("rjrn-atomic-write-doc");
+//!    fs::create_dir_all(&dir).unwrap();
+//!    let path = dir.join("journal.json");
+//!
+//!    atomic_write::write(&path, b"[1]").unwrap();
+//!    atomic_write::write(&path, b"[1,2]").unwrap();
+//!    assert_eq!(fs::read_to_string(&path).unwrap(), "[1,2]");
+//!    assert_eq!(fs::read_to_string(atomic_write::backup_path(&path, 0)).unwrap(), "[1]");
+//!
+//!    fs::remove_dir_all(&dir).unwrap();
+//!```
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+// How many previous versions of a file are kept
+pub const BACKUPS: usize = 3;
+
+pub fn write(path: &Path, contents: &[u8]) -> Result<(), String> {
+    save(path, contents, true)
+}
+
+// Without backups, for files where they'd be clutter like the entries of a
+// markdown directory
+pub fn replace(path: &Path, contents: &[u8]) -> Result<(), String> {
+    save(path, contents, false)
+}
+
+fn save(path: &Path, contents: &[u8], backup: bool) -> Result<(), String> {
+    let tmp = sibling(path, &format!(".tmp-{}", process::id()));
+
+    let written = open_private(&tmp)
+        .and_then(|mut file| {
+            if let Ok(metadata) = fs::metadata(path) {
+                file.set_permissions(metadata.permissions())?;
+            }
+            file.write_all(contents)?;
+            file.sync_all()
+        });
+
+    if let Err(why) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(format!("Couldn't write {} because: {}",
+                           tmp.display(), why));
+    }
+
+    if backup && path.exists() {
+        if let Err(why) = rotate_backups(path) {
+            let _ = fs::remove_file(&tmp);
+            return Err(why);
+        }
+    }
+
+    if let Err(why) = fs::rename(&tmp, path) {
+        let _ = fs::remove_file(&tmp);
+        return Err(format!("Couldn't replace {} because: {}",
+                           path.display(), why));
+    }
+
+    sync_dir(path);
+    Ok(())
+}
+
+// `<path>.bak` is the newest backup, `<path>.bak.<n>` are older ones
+pub fn backup_path(path: &Path, n: usize) -> PathBuf {
+    if n == 0 { sibling(path, ".bak") } else { sibling(path, &format!(".bak.{}", n)) }
+}
+
+fn rotate_backups(path: &Path) -> Result<(), String> {
+    for n in (1..BACKUPS).rev() {
+        let older = backup_path(path, n - 1);
+        if older.exists() {
+            fs::rename(&older, backup_path(path, n))
+            .map_err(|why| format!("Couldn't rotate backup {} because: {}",
+                                   older.display(), why))?;
+        }
+    }
+
+    // A hard link keeps the old content once the new file is renamed over it,
+    // copying is only needed where links aren't supported
+    let backup = backup_path(path, 0);
+    fs::hard_link(path, &backup)
+        .or_else(|_| fs::copy(path, &backup).map(|_| ()))
+        .map_err(|why| format!("Couldn't back up {} because: {}",
+                               path.display(), why))
+}
+
+#[cfg(unix)]
+fn open_private(path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)
+}
+
+#[cfg(not(unix))]
+fn open_private(path: &Path) -> io::Result<File> {
+    OpenOptions::new().write(true).create(true).truncate(true).open(path)
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+// Makes the rename itself durable; not every platform can open a directory
+fn sync_dir(path: &Path) {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new(".")
+    };
+
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use test_support::TempDir;
+    use super::{backup_path, replace, write, BACKUPS};
+
+    #[test]
+    fn only_the_last_versions_are_kept() {
+        let dir = TempDir::new("atomic-write");
+        fs::create_dir_all(dir.join("")).unwrap();
+        let path = dir.join("journal.json");
+        for n in 0..6 {
+            write(&path, n.to_string().as_bytes()).unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "5");
+        for n in 0..BACKUPS {
+            assert_eq!(fs::read_to_string(backup_path(&path, n)).unwrap(), (4 - n).to_string());
+        }
+        assert!(!backup_path(&path, BACKUPS).exists());
+        assert_eq!(fs::read_dir(dir.join("")).unwrap().count(), BACKUPS + 1);
+    }
+
+    #[test]
+    fn replaced_files_have_no_backups() {
+        let dir = TempDir::new("atomic-write");
+        fs::create_dir_all(dir.join("")).unwrap();
+        let path = dir.join("entry.md");
+        replace(&path, b"first").unwrap();
+        replace(&path, b"second").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        assert_eq!(fs::read_dir(dir.join("")).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn failed_writes_leave_nothing_behind() {
+        let dir = TempDir::new("atomic-write");
+        assert!(write(&dir.join("missing/journal.json"), b"[]").unwrap_err().contains("Couldn't write"));
+
+        // A directory can't be replaced by a file
+        fs::create_dir_all(dir.join("journal.json")).unwrap();
+        assert!(replace(&dir.join("journal.json"), b"[]").unwrap_err().contains("Couldn't replace"));
+        assert_eq!(fs::read_dir(dir.join("")).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn new_files_are_private_and_old_ones_keep_their_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new("atomic-write");
+        fs::create_dir_all(dir.join("")).unwrap();
+        let path = dir.join("journal.json");
+        write(&path, b"[]").unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        write(&path, b"[1]").unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+    }
+}
@@ -1,10 +1,22 @@
 use std::io;
 use std::io::prelude::*;
-use rjrn::entry::{Entry, EntryBuilder};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use rjrn::entry::{Entry, EntryBuilder, normalize_tag, saved_as};
 use rjrn::config::Config;
-use rjrn::file_journal::FileJournal;
-use rjrn::journal::Journal;
+use rjrn::journal::{Journal, JournalOptions};
+use rjrn::search::{Matcher, Mode};
+use rjrn::query::Query;
+use rjrn::front_matter::{self, Value};
+use rjrn::prompt;
+use rjrn::trash;
+use rjrn::operation_log::Change;
+use uuid::Uuid;
+use chrono::*;
+use editor;
 use cli_args::Args;
+use output;
 
 fn handle_version() -> Result<(), String> {
     println!("{}", env!("CARGO_PKG_VERSION"));
@@ -12,15 +24,300 @@ fn handle_version() -> Result<(), String> {
 }
 
 fn handle_add_journal(config: &mut Config) -> Result<(), String> {
-    try!(config.add_journal());
+    config.add_journal()?;
     match config.save() {
         Ok(_) => Ok(()),
         Err(_) => Err("Couldn't add journal".to_string())
     }
 }
 
+// `~/` is expanded and relative paths are taken from the current directory,
+// as rjrn runs from anywhere
+fn absolute_path(path: &str) -> String {
+    let path = match (path.starts_with("~/"), env::home_dir()) {
+        (true, Some(home)) => home.join(&path[2..]),
+        _ => Path::new(path).to_path_buf()
+    };
+
+    match env::current_dir() {
+        Ok(dir) => dir.join(path).to_string_lossy().into_owned(),
+        Err(_) => path.to_string_lossy().into_owned()
+    }
+}
+
+// Without `--type` the journal is added with prompts, like `rjrn --add`.
+// Prompts would ignore the other flags and hang without a terminal.
+fn add_journal(config: &mut Config, args: &Args) -> Result<(), String> {
+    let other_flags = !args.flag_name.is_empty() || !args.flag_path.is_empty() || !args.flag_option.is_empty();
+    if args.flag_type.is_empty() && (other_flags || !output::stdin_is_tty()) {
+        return Err("--type is required to add a journal without prompts".to_string());
+    }
+
+    if args.flag_type.is_empty() {
+        config.add_journal()?;
+        if args.flag_default {
+            let name = config.journals.last().unwrap().name().clone();
+            config.set_default_journal(&name)?;
+        }
+        return config.save();
+    }
+
+    let mut options = JournalOptions::new(&args.flag_name);
+    options.path = if args.flag_path.is_empty() { None } else { Some(absolute_path(&args.flag_path)) };
+    for option in args.flag_option.iter() {
+        match option.find('=') {
+            Some(i) => {
+                let key = option[..i].replace("-", "_");
+                // `-` reads secrets like tokens from stdin, out of the shell history
+                let value = if &option[i + 1..] == "-" {
+                    read_stdin_line(&key)?
+                } else {
+                    option[i + 1..].to_string()
+                };
+                options.settings.insert(key, value);
+            },
+            None => return Err(format!("Expected --option <key>=<value>, got {}", option))
+        }
+    }
+    // Repositories are paths too
+    if let Some(repository) = options.settings.get("repository").cloned() {
+        options.settings.insert("repository".to_string(), absolute_path(&repository));
+    }
+
+    config.add_journal_with_options(&args.flag_type, &options, args.flag_default)?;
+    config.save()?;
+    println!("Journal {} added.", options.name);
+    Ok(())
+}
+
+fn read_stdin_line(key: &str) -> Result<String, String> {
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)
+    .map_err(|why| format!("Couldn't read {} from stdin because: {}", key, why))?;
+
+    match line.trim() {
+        "" => Err(format!("Expected {} on stdin", key)),
+        value => Ok(value.to_string())
+    }
+}
+
+fn list_journals(config: &Config) -> Result<(), String> {
+    for journal in config.journals.iter() {
+        let default = if journal.is_default() { "*" } else { " " };
+        let kind = journal.to_json().get("type").and_then(|t| t.as_str()).unwrap_or("").to_string();
+        let mut line = format!("{} {}  {}", default, journal.name(), kind);
+
+        if let Some(path) = config.journal_path(journal.name())? {
+            line = format!("{}  {}", line, path);
+        }
+        let aliases = config.aliases(journal.name());
+        if !aliases.is_empty() {
+            line = format!("{}  [{}]", line, aliases.join(", "));
+        }
+        println!("{}", line);
+    }
+
+    if let Err(why) = config.validate() {
+        println!("{}", why);
+    }
+    Ok(())
+}
+
+// Settings of the journal, without the ones like Trello tokens that are secrets
+fn show_journal(config: &Config, name: &str) -> Result<(), String> {
+    let journal = match config.journals.iter().find(|j| j.name() == name) {
+        Some(journal) => journal,
+        None => return Err(format!("There's no journal named {}", name))
+    };
+    let json = journal.to_json();
+
+    println!("name:     {}", journal.name());
+    println!("type:     {}", json.get("type").and_then(|t| t.as_str()).unwrap_or(""));
+    println!("default:  {}", journal.is_default());
+    if let Some(repository) = json.get("repository").and_then(|r| r.as_str()) {
+        println!("git:      {}", repository);
+    }
+    if let Some(path) = config.journal_path(name)? {
+        println!("path:     {}", path);
+    }
+    println!("aliases:  {}", config.aliases(name).join(", "));
+    println!("entries:  {}", journal.entries()?.len());
+    println!("in trash: {}", config.trash(journal).entries()?.len());
+    Ok(())
+}
+
+fn remove_journal(config: &mut Config, args: &Args) -> Result<(), String> {
+    let path = config.journal_path(&args.arg_name)?;
+    let question = match (args.flag_delete_file, &path) {
+        (true, Some(path)) => format!("Remove the journal {} and delete {}? (y/N)", args.arg_name, path),
+        _ => format!("Remove the journal {} from the config? (y/N)", args.arg_name)
+    };
+    if !args.flag_yes && !prompt::confirm(&question) {
+        println!("Nothing was removed.");
+        return Ok(());
+    }
+
+    let journal = config.remove_journal(&args.arg_name)?;
+    config.save()?;
+
+    match (args.flag_delete_file, path) {
+        (true, Some(path)) => {
+            if delete_journal_files(&journal, Path::new(&path))? {
+                println!("Journal {} removed, {} deleted.", args.arg_name, path);
+            } else {
+                println!("Journal {} removed, its entries were deleted from {}.", args.arg_name, path);
+            }
+        },
+        (_, Some(path)) => println!("Journal {} removed, its entries are still in {}.", args.arg_name, path),
+        (_, None) => println!("Journal {} removed.", args.arg_name)
+    }
+    Ok(())
+}
+
+// Only the files the journal owns are deleted, so a directory holding other
+// files than its entries is kept. Its trash goes with it. Tells if `path`
+// itself is gone.
+// The trash of the journal is deleted along with it by `Config::remove_journal`
+fn delete_journal_files(journal: &Box<dyn Journal>, path: &Path) -> Result<bool, String> {
+    for file in journal.files().iter() {
+        debug!("Deleting {}", file.display());
+        fs::remove_file(file)
+        .map_err(|why| format!("Couldn't delete {} because: {}",
+                               file.display(), why))?;
+    }
+
+    if path.is_dir() {
+        match fs::read_dir(path).map(|mut files| files.next().is_none()) {
+            Ok(true) => fs::remove_dir(path)
+                        .map_err(|why| format!("Couldn't delete {} because: {}",
+                                               path.display(), why))?,
+            _ => {
+                println!("{} has other files, it was kept.", path.display());
+                return Ok(false);
+            }
+        }
+    }
+    Ok(!path.exists())
+}
+
+// The journal's file and the ones next to it named after it, like backups,
+// locks and SQLite's `-wal`. Directories are moved with everything in them.
+fn journal_moves(journal: &Box<dyn Journal>, old: &Path, new: &Path) -> Vec<(PathBuf, PathBuf)> {
+    let mut moves = vec![(old.to_path_buf(), new.to_path_buf())];
+    let old_name = old.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let new_name = new.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+
+    for file in journal.files().into_iter() {
+        let name = file.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        if file != old && file.parent() == old.parent() && name.starts_with(&old_name) {
+            let to = new.with_file_name(format!("{}{}", new_name, &name[old_name.len()..]));
+            moves.push((file, to));
+        }
+    }
+    moves
+}
+
+// Renaming fails across file systems, the files are copied and removed then
+fn move_file(from: &Path, to: &Path) -> Result<(), String> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    if let Err(why) = copy_all(from, to) {
+        let _ = remove_all(to);
+        return Err(format!("Couldn't move {} to {} because: {}",
+                           from.display(), to.display(), why));
+    }
+    if let Err(why) = remove_all(from) {
+        warn!("{} was copied to {}, but couldn't be removed: {}",
+              from.display(), to.display(), why);
+    }
+    Ok(())
+}
+
+fn copy_all(from: &Path, to: &Path) -> io::Result<()> {
+    if !from.is_dir() {
+        return fs::copy(from, to).map(|_| ());
+    }
+
+    fs::create_dir(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        copy_all(&entry.path(), &to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+fn remove_all(path: &Path) -> io::Result<()> {
+    if path.is_dir() { fs::remove_dir_all(path) } else { fs::remove_file(path) }
+}
+
+// Moved files go back when a later one or saving the config fails, so the
+// config never points to where the journal isn't
+fn move_journal_path(config: &mut Config, args: &Args) -> Result<(), String> {
+    let old = match config.journal_path(&args.arg_name)? {
+        Some(path) => path,
+        None => return Err(format!("The journal {} isn't kept in a file", args.arg_name))
+    };
+    let new = absolute_path(&args.arg_path);
+
+    let moves = match config.journals.iter().find(|j| *j.name() == args.arg_name) {
+        Some(journal) if args.flag_move_file => journal_moves(journal, Path::new(&old), Path::new(&new)),
+        _ => vec![]
+    };
+    if let Some((_, to)) = moves.iter().find(|&(_, to)| to.exists()) {
+        return Err(format!("{} already exists", to.display()));
+    }
+
+    for (i, (from, to)) in moves.iter().enumerate() {
+        if let Err(why) = move_file(from, to) {
+            move_back(&moves[..i]);
+            return Err(why);
+        }
+    }
+
+    if let Err(why) = config.set_journal_path(&args.arg_name, &new).and_then(|_| config.save()) {
+        move_back(&moves);
+        return Err(why);
+    }
+    println!("Journal {} is now at {}", args.arg_name, new);
+    Ok(())
+}
+
+fn move_back(moves: &[(PathBuf, PathBuf)]) {
+    for (from, to) in moves.iter().rev() {
+        if let Err(why) = move_file(to, from) {
+            warn!("Couldn't move {} back: {}", to.display(), why);
+        }
+    }
+}
+
+fn journal_command(config: &mut Config, args: &Args) -> Result<(), String> {
+    if args.cmd_add { return add_journal(config, args); }
+    if args.cmd_list { return list_journals(config); }
+    if args.cmd_show { return show_journal(config, &args.arg_name); }
+    if args.cmd_remove { return remove_journal(config, args); }
+    if args.cmd_move_path {
+        config.validate()?;
+        return move_journal_path(config, args);
+    }
+
+    if args.cmd_rename {
+        config.validate()?;
+        config.rename_journal(&args.arg_old, &args.arg_new)?;
+        println!("Journal {} renamed to {}", args.arg_old, args.arg_new);
+        return Ok(());
+    }
+
+    if args.cmd_set_default {
+        config.set_default_journal(&args.arg_name)?;
+        println!("{} is the default journal", args.arg_name);
+    }
+    config.save()
+}
+
+// Piped input, e.g. `echo foo | rjrn`
 fn get_content_from_cli() -> String {
-    println!("Please write your entry:");
     let stdin = io::stdin();
     let mut lines: Vec<String> = vec![];
 
@@ -31,47 +328,822 @@ fn get_content_from_cli() -> String {
     lines.join("\n")
 }
 
-fn get_journal<'a>(config: &'a Config, name: &String) -> Result<&'a Box<FileJournal>, String> {
-    match config.file_journal_with_name_or_default(name) {
+const COMPOSE_HELP: &str = "\
+# Write the entry below the second `---`, saving an empty entry cancels it.
+# A `null` title is taken from the first sentence, tags are written as [work, home].
+";
+
+// Fills the builder from what's saved in the editor, false when the entry is empty
+fn compose_in_editor(builder: &mut EntryBuilder, args: &Args) -> Result<bool, String> {
+    let title = if args.flag_title.is_empty() {
+        Value::Null
+    } else {
+        Value::Scalar(args.flag_title.clone())
+    };
+
+    let header = front_matter::render(&vec![
+        ("title".to_string(), title),
+        ("tags".to_string(), Value::List(args.flag_tag.iter().map(|t| normalize_tag(t)).collect())),
+        ("starred".to_string(), Value::Scalar(args.flag_star.to_string())),
+    ], "");
+    // Comments go right after the opening `---`, the front matter skips them
+    let template = format!("---\n{}{}", COMPOSE_HELP, &header[4..]);
+
+    let (fields, body) = front_matter::parse(&editor::edit(&template, "new-entry.md")?)?;
+    if body.trim().is_empty() {
+        return Ok(false);
+    }
+
+    match fields.get("title").and_then(|t| t.as_str()) {
+        Some(title) if !title.trim().is_empty() => { builder.title(title.to_string()); },
+        _ => { builder.title("".to_string()); }
+    }
+    builder.starred(fields.get("starred").and_then(|s| s.as_bool()).unwrap_or(false))
+        .tags(vec![]);
+    for tag in fields.get("tags").map(|t| t.as_list()).unwrap_or(vec![]).iter() {
+        builder.tag(tag);
+    }
+    builder.content(body.trim().to_string());
+
+    Ok(true)
+}
+
+fn get_journal<'a>(config: &'a Config, name: &str) -> Result<&'a Box<dyn Journal>, String> {
+    match config.journal_with_name_or_default(name) {
         None => Err("Please add a journal".to_string()),
         Some(j) => Ok(j)
     }
 }
 
-fn add_new_entry(journal: &Box<FileJournal>, args: &Args) -> Result<(), String> {
-    let content: String = match args.arg_content.len() {
-        0 => get_content_from_cli(),
-        _ =>  args.arg_content.join(" ")
-    };
+fn add_new_entry(config: &Config, journal: &Box<dyn Journal>, content: &Vec<String>, args: &Args) -> Result<(), String> {
+    let mut builder = EntryBuilder::new();
+    builder.starred(args.flag_star)
+        .title(args.flag_title.clone());
+    for tag in args.flag_tag.iter() {
+        builder.tag(tag);
+    }
+
+    if content.is_empty() && output::stdin_is_tty() {
+        if !compose_in_editor(&mut builder, args)? {
+            println!("The entry is empty, nothing was saved.");
+            return Ok(());
+        }
+    } else {
+        let content: String = match content.len() {
+            0 => get_content_from_cli(),
+            _ =>  content.join(" ")
+        };
+        builder.content(content);
+    }
 
-    let entry: Entry = try!(EntryBuilder::new()
-                            .starred(args.flag_star)
-                            .title(args.flag_title.clone())
-                            .content(content)
-                            .finalize());
+    let entry: Entry = builder.finalize()?;
 
-    let id = entry.id().clone();
+    let added = entry.clone();
     match journal.upsert_entry(entry) {
-        Ok(_) => {
+        Ok(id) => {
             println!("entry add id: {:?}", id);
-            Ok(())
+            let added = saved_as(added, id);
+            config.record(journal, &format!("Add entry {}", describe(&added)),
+                   vec![Change::new(journal.name(), None, Some(added))])
         },
         Err(why) => Err(format!("Couldn't add entry because: {}", why))
     }
 }
 
-fn undo_last_entry(journal: &Box<FileJournal>) -> Result<(), String> {
-    journal.undo_last_entry()
+// `rjrn work fixed the build` saves `fixed the build` in the journal named or
+// aliased `work`. Picking a journal with `--journal` or passing `--no-route`
+// keeps the first word in the entry.
+fn route_entry<'a>(config: &'a Config, args: &Args) -> Result<(&'a Box<dyn Journal>, Vec<String>), String> {
+    let content = args.arg_content.clone();
+    if !args.flag_journal.is_empty() || args.flag_no_route {
+        return Ok((get_journal(config, &args.flag_journal)?, content));
+    }
+
+    let text = content.join(" ");
+    let text = text.trim_start();
+    let (first, rest) = match text.find(char::is_whitespace) {
+        Some(i) => (&text[..i], text[i..].trim_start()),
+        None => (text, "")
+    };
+
+    match config.route(first) {
+        Some(journal) => {
+            debug!("Routing the entry to {}", journal.name());
+            Ok((journal, if rest.is_empty() { vec![] } else { vec![rest.to_string()] }))
+        },
+        None => Ok((get_journal(config, &args.flag_journal)?, content))
+    }
+}
+
+// `a1b2c3d4 Title`, to describe operations
+fn describe(entry: &Entry) -> String {
+    format!("{} {}", output::id_prefix(entry), output::truncate(&output::title(entry), 40))
+}
+
+
+// Last `--count` entries matching the query, oldest first so the newest one
+// ends up next to the prompt
+fn listed_entries(journal: &Box<dyn Journal>, query: &Query, args: &Args) -> Result<Vec<Box<Entry>>, String> {
+    let mut entries: Vec<Box<Entry>> = journal.query_entries(query)?
+        .into_iter()
+        .filter(|e| !args.flag_starred || e.starred())
+        .collect();
+    entries.sort_by_key(|a| a.created_at());
+
+    let skip = entries.len().saturating_sub(args.flag_count);
+    Ok(entries.into_iter().skip(skip).collect())
+}
+
+fn list_journal(journal: &Box<dyn Journal>, query: &Query, args: &Args, width: usize) -> Result<(), String> {
+    for entry in listed_entries(journal, query, args)?.iter() {
+        output::print_entry(entry, args.flag_full, width);
+    }
+
+    Ok(())
+}
+
+fn list_entries(config: &Config, args: &Args) -> Result<(), String> {
+    let width = output::terminal_width();
+    let query = Query::from_args(&args.arg_query)?;
+
+    if !args.flag_all && !query.has_journal() {
+        return list_journal(get_journal(config, &args.flag_journal)?, &query, args, width);
+    }
+
+    let journals: Vec<&Box<dyn Journal>> = config.journals.iter()
+        .filter(|j| query.matches_journal(j.name()))
+        .collect();
+
+    for (i, journal) in journals.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("{}:", journal.name());
+        list_journal(journal, &query, args, width)?;
+    }
+
+    Ok(())
+}
+
+// Prints every matching entry with the lines of its content containing a hit
+fn search_journal(journal: &Box<dyn Journal>, matcher: &Matcher, width: usize, tty: bool) -> Result<usize, String> {
+    let mut entries: Vec<Box<Entry>> = journal.entries()?
+        .into_iter()
+        .filter(|e| matcher.matches_entry(e))
+        .collect();
+    entries.sort_by_key(|a| a.created_at());
+
+    for entry in entries.iter() {
+        // Only the title, an id or date matching the pattern isn't a hit
+        let line = output::entry_line(entry, width);
+        let (from, to) = output::title_range(entry, &line);
+        let hits = matcher.find_all(&line[from..to]).into_iter().map(|(a, b)| (a + from, b + from)).collect();
+        println!("{}", output::highlight(&line, &hits, tty));
+
+        for line in entry.content().lines() {
+            let line = output::truncate(&format!("    {}", line), width);
+            let hits = matcher.find_all(&line);
+            if !hits.is_empty() {
+                println!("{}", output::highlight(&line, &hits, tty));
+            }
+        }
+    }
+
+    Ok(entries.len())
+}
+
+fn search_entries(config: &Config, args: &Args) -> Result<(), String> {
+    let mode = if args.flag_regex {
+        Mode::Regex
+    } else if args.flag_word {
+        Mode::Word
+    } else {
+        Mode::Substring
+    };
+    let matcher = Matcher::new(&args.arg_pattern, mode)?;
+    let width = output::terminal_width();
+    let tty = output::stdout_is_tty();
+
+    let mut found = 0;
+    for journal in selected_journals(config, args)?.iter() {
+        if args.flag_all {
+            println!("{}:", journal.name());
+        }
+        found += search_journal(journal, &matcher, width, tty)?;
+    }
+
+    if found == 0 {
+        return Err(format!("No entries match: {}", args.arg_pattern));
+    }
+
+    Ok(())
+}
+
+// The only entry with an id starting with `prefix`, dashes don't matter
+fn find_entry(journal: &Box<dyn Journal>, prefix: &str) -> Result<Box<Entry>, String> {
+    unique_by_prefix(journal.entries()?, prefix, |e| e.id(), journal.name())
+}
+
+// The only item with an id starting with `prefix`, `place` is used in errors
+fn unique_by_prefix<T, F>(items: Vec<T>, prefix: &str, id: F, place: &str) -> Result<T, String>
+    where F: Fn(&T) -> &Uuid {
+    let prefix = prefix.replace("-", "").to_lowercase();
+    if prefix.is_empty() {
+        return Err("Please give the id of the entry".to_string());
+    }
+
+    let mut found: Vec<T> = items.into_iter()
+        .filter(|item| id(item).simple().to_string().starts_with(&prefix))
+        .collect();
+
+    match found.len() {
+        0 => Err(format!("No entry with id starting with {} in {}", prefix, place)),
+        1 => Ok(found.remove(0)),
+        n => Err(format!("{} entries have an id starting with {}, please give a longer one", n, prefix))
+    }
+}
+
+fn entry_to_front_matter(entry: &Entry) -> String {
+    let title = match *entry.title() {
+        Some(ref title) => Value::Scalar(title.clone()),
+        None => Value::Null
+    };
+
+    front_matter::render(&vec![
+        ("title".to_string(), title),
+        ("tags".to_string(), Value::List(entry.tags().clone())),
+        ("starred".to_string(), Value::Scalar(entry.starred().to_string())),
+    ], entry.content())
+}
+
+// Opens the entry in the editor and saves it back under the same id
+fn edit_entry(config: &Config, journal: &Box<dyn Journal>, args: &Args) -> Result<(), String> {
+    let entry = find_entry(journal, &args.arg_id)?;
+    let text = entry_to_front_matter(&entry);
+    let edited = editor::edit(&text, &format!("{}.md", output::id_prefix(&entry)))?;
+
+    if edited == text {
+        println!("Nothing changed.");
+        return Ok(());
+    }
+
+    let updated = edited_entry(&entry, &edited)?;
+    let after = updated.clone();
+    match journal.upsert_entry(updated) {
+        Ok(id) => {
+            println!("entry updated id: {:?}", id);
+            let after = saved_as(after, id);
+            config.record(journal, &format!("Edit entry {}", describe(&after)),
+                   vec![Change::new(journal.name(), Some((*entry).clone()), Some(after))])
+        },
+        Err(why) => Err(format!("Couldn't update entry because: {}", why))
+    }
+}
+
+// The entry as changed in the editor. Tags written in the text are added
+// like they are to new entries.
+fn edited_entry(entry: &Entry, edited: &str) -> Result<Entry, String> {
+    let (fields, body) = front_matter::parse(edited)?;
+    let mut builder = EntryBuilder::new();
+    builder.id(*entry.id())
+        .raw_content(body)
+        .created_at(entry.created_at())
+        .updated_at(Utc::now())
+        .starred(fields.get("starred").and_then(|s| s.as_bool()).unwrap_or(false))
+        .tags(fields.get("tags").map(|t| t.as_list()).unwrap_or(vec![]));
+
+    match fields.get("title").and_then(|t| t.as_str()) {
+        Some(title) if !title.trim().is_empty() => { builder.title(title.to_string()); },
+        _ => { builder.set_title_from_content(); }
+    }
+
+    builder.tags_from_text().finalize()
+}
+
+// Earlier versions of the entry, oldest first, the current one at the end
+fn show_history(entry: &Entry, args: &Args) {
+    let width = output::terminal_width();
+
+    for (i, revision) in entry.revisions().iter().enumerate() {
+        let title = match *revision.title() {
+            Some(ref title) => title.clone(),
+            None => revision.content().lines().next().unwrap_or("").to_string()
+        };
+        println!("{}", output::summary_line(&format!("{:>8}", i + 1), revision.updated_at(),
+                                            revision.starred(), &title, revision.tags(), width));
+        if args.flag_full {
+            output::print_content(revision.content());
+        }
+    }
+
+    println!("{}", output::summary_line(&format!("{:>8}", "current"), entry.updated_at(),
+                                        entry.starred(), &output::title(entry), entry.tags(), width));
+    if args.flag_full {
+        output::print_content(entry.content());
+    }
+}
+
+// Saves revision `n` as the current version, the replaced one becomes a revision
+fn restore_revision(config: &Config, journal: &Box<dyn Journal>, entry: &Entry, n: usize) -> Result<(), String> {
+    let revision = match entry.revisions().get(n.wrapping_sub(1)) {
+        Some(revision) => revision,
+        None => return Err(format!("There's no revision {}, the entry has {}", n,
+                                   entry.revisions().len()))
+    };
+
+    let mut builder = EntryBuilder::new();
+    builder.id(*entry.id())
+        .raw_content(revision.content().clone())
+        .created_at(entry.created_at())
+        .updated_at(Utc::now())
+        .starred(revision.starred())
+        .tags(revision.tags().clone());
+
+    match *revision.title() {
+        Some(ref title) => { builder.title(title.clone()); },
+        None => { builder.set_title_from_content(); }
+    }
+
+    let restored = builder.finalize()?;
+    let id = journal.upsert_entry(restored.clone())?;
+    println!("entry restored to revision {} id: {:?}", n, id);
+    let restored = saved_as(restored, id);
+    config.record(journal, &format!("Restore revision {} of entry {}", n, describe(entry)),
+           vec![Change::new(journal.name(), Some(entry.clone()), Some(restored))])
+}
+
+fn entry_history(config: &Config, journal: &Box<dyn Journal>, args: &Args) -> Result<(), String> {
+    let entry = find_entry(journal, &args.arg_id)?;
+
+    if !args.flag_restore.is_empty() {
+        let n = args.flag_restore.parse::<usize>()
+                .map_err(|_| format!("Expected the number of a revision, got {}", args.flag_restore))?;
+        return restore_revision(config, journal, &entry, n);
+    }
+
+    if entry.revisions().is_empty() {
+        println!("The entry has no earlier revisions.");
+    }
+    show_history(&entry, args);
+    Ok(())
+}
+
+// Journals a command works on, every journal with `--all`
+fn selected_journals<'a>(config: &'a Config, args: &Args) -> Result<Vec<&'a Box<dyn Journal>>, String> {
+    if args.flag_all {
+        Ok(config.journals.iter().collect())
+    } else {
+        Ok(vec![get_journal(config, &args.flag_journal)?])
+    }
+}
+
+fn list_tags(config: &Config, args: &Args) -> Result<(), String> {
+    for journal in selected_journals(config, args)?.iter() {
+        if args.flag_all {
+            println!("{}:", journal.name());
+        }
+        for (tag, count) in journal.tag_counts()?.into_iter() {
+            println!("{:>6}  {}", count, tag);
+        }
+    }
+
+    Ok(())
+}
+
+// Entries are read before and after the merge, so it can be undone
+fn merge_tags(config: &Config, args: &Args, from: &Vec<String>, into: &str, description: String) -> Result<(), String> {
+    let tags: Vec<String> = from.iter().map(|t| normalize_tag(t)).collect();
+
+    for journal in selected_journals(config, args)?.iter() {
+        let before: Vec<Box<Entry>> = journal.entries()?
+            .into_iter()
+            .filter(|e| e.tags().iter().any(|t| tags.contains(t)))
+            .collect();
+
+        let changed = journal.merge_tags(from, into)?;
+        println!("{}: {} entries changed", journal.name(), changed);
+
+        let after = journal.entries()?;
+        let changes = before.into_iter()
+            .filter_map(|b| after.iter().find(|a| a.id() == b.id())
+                        .map(|a| Change::new(journal.name(), Some(*b.clone()), Some((**a).clone()))))
+            .collect();
+        config.record(journal, &description, changes)?;
+    }
+
+    Ok(())
+}
+
+// A rename into a tag already in use would merge them, that's what `tag merge` is for
+fn rename_tag(config: &Config, args: &Args) -> Result<(), String> {
+    let new = normalize_tag(&args.arg_new);
+    for journal in selected_journals(config, args)?.iter() {
+        if journal.tag_counts()?.iter().any(|(tag, _)| *tag == new) {
+            return Err(format!("{} already has the tag {}, use `rjrn tag merge` to merge them",
+                               journal.name(), new));
+        }
+    }
+
+    merge_tags(config, args, &vec![args.arg_old.clone()], &new,
+               format!("Rename tag {} to {}", args.arg_old, new))
+}
+
+// The last tag is the one the others are merged into
+fn merge_tags_command(config: &Config, args: &Args) -> Result<(), String> {
+    if args.arg_tags.len() < 2 {
+        return Err("Please give the tags to merge and the tag to merge them into".to_string());
+    }
+
+    let (into, from) = args.arg_tags.split_last().unwrap();
+    merge_tags(config, args, &from.to_vec(), into,
+               format!("Merge tags {} into {}", from.join(", "), into))
+}
+
+fn change_entry_tag(config: &Config, journal: &Box<dyn Journal>, args: &Args) -> Result<(), String> {
+    let entry = find_entry(journal, &args.arg_id)?;
+    let tag = normalize_tag(&args.arg_tag);
+
+    let mut builder = EntryBuilder::from_entry(&entry);
+    if args.cmd_add {
+        builder.tag(&tag);
+    } else {
+        builder.tags(entry.tags().iter().filter(|t| **t != tag).cloned().collect());
+    }
+
+    let updated = builder.updated_at(Utc::now()).finalize()?;
+    if updated.tags() == entry.tags() {
+        println!("Nothing changed.");
+        return Ok(());
+    }
+
+    let id = journal.upsert_entry(updated.clone())?;
+    println!("entry updated id: {:?}", id);
+    let updated = saved_as(updated, id);
+    let description = if args.cmd_add {
+        format!("Add tag {} to entry {}", tag, describe(&entry))
+    } else {
+        format!("Remove tag {} from entry {}", tag, describe(&entry))
+    };
+    config.record(journal, &description,
+           vec![Change::new(journal.name(), Some((*entry).clone()), Some(updated))])
+}
+
+fn tag_command(config: &Config, args: &Args) -> Result<(), String> {
+    if args.cmd_rename { return rename_tag(config, args); }
+    if args.cmd_merge { return merge_tags_command(config, args); }
+
+    change_entry_tag(config, get_journal(config, &args.flag_journal)?, args)
+}
+
+// Entries found in one journal
+type JournalEntries<'a> = (&'a Box<dyn Journal>, Vec<Box<Entry>>);
+
+// Entries to remove, grouped by journal
+fn entries_to_remove<'a>(config: &'a Config, args: &Args) -> Result<Vec<JournalEntries<'a>>, String> {
+    if args.flag_where.is_empty() {
+        let journal = get_journal(config, &args.flag_journal)?;
+        let mut entries: Vec<Box<Entry>> = vec![];
+        for prefix in args.arg_ids.iter() {
+            let entry = find_entry(journal, prefix)?;
+            if !entries.iter().any(|e| e.id() == entry.id()) {
+                entries.push(entry);
+            }
+        }
+        return Ok(vec![(journal, entries)]);
+    }
+
+    let query = Query::parse(&args.flag_where)?;
+    let journals: Vec<&Box<dyn Journal>> = if args.flag_all || query.has_journal() {
+        config.journals.iter().filter(|j| query.matches_journal(j.name())).collect()
+    } else {
+        vec![get_journal(config, &args.flag_journal)?]
+    };
+
+    let mut result = vec![];
+    for journal in journals.into_iter() {
+        let entries = journal.query_entries(&query)?;
+        if !entries.is_empty() {
+            result.push((journal, entries));
+        }
+    }
+
+    Ok(result)
+}
+
+fn remove_entries(config: &Config, args: &Args) -> Result<(), String> {
+    let to_remove = entries_to_remove(config, args)?;
+    let count = to_remove.iter().fold(0, |sum, (_, entries)| sum + entries.len());
+    if count == 0 {
+        return Err("No entries match, nothing was removed".to_string());
+    }
+
+    let width = output::terminal_width();
+    for &(journal, ref entries) in to_remove.iter() {
+        println!("{}:", journal.name());
+        for entry in entries.iter() {
+            println!("{}", output::entry_line(entry, width));
+        }
+    }
+
+    if args.flag_dry_run {
+        println!("Dry run, {} entries would be moved to the trash.", count);
+        return Ok(());
+    }
+
+    if !args.flag_yes && !prompt::confirm(&format!("Move {} entries to the trash? (y/N)", count)) {
+        println!("Nothing was removed.");
+        return Ok(());
+    }
+
+    for (journal, entries) in to_remove.into_iter() {
+        let changes = entries.iter().map(|e| Change::new(journal.name(), Some((**e).clone()), None)).collect();
+        let description = format!("Remove {} entries", entries.len());
+        config.trash_entries(journal, entries)?;
+        config.record(journal, &description, changes)?;
+    }
+    println!("{} entries moved to the trash, see `rjrn trash`.", count);
+    Ok(())
+}
+
+
+fn list_trash(config: &Config, args: &Args) -> Result<(), String> {
+    let width = output::terminal_width();
+    for journal in selected_journals(config, args)?.iter() {
+        let trashed = config.trash(journal).entries()?;
+        if args.flag_all {
+            println!("{}:", journal.name());
+        } else if trashed.is_empty() {
+            println!("The trash of {} is empty.", journal.name());
+        }
+
+        // Dates are when the entries were removed
+        for t in trashed.iter() {
+            let entry = t.entry();
+            println!("{}", output::summary_line(&output::id_prefix(entry), t.deleted_at(),
+                                                entry.starred(), &output::title(entry),
+                                                entry.tags(), width));
+        }
+    }
+    Ok(())
+}
+
+fn empty_trash(config: &Config, args: &Args) -> Result<(), String> {
+    let older_than = if args.flag_older_than.is_empty() {
+        None
+    } else {
+        Some(trash::parse_age(&args.flag_older_than)?)
+    };
+
+    if !args.flag_yes {
+        let question = match older_than {
+            Some(_) => format!("Purge entries removed more than {} ago? (y/N)", args.flag_older_than),
+            None => "Purge every entry in the trash? (y/N)".to_string()
+        };
+        if !prompt::confirm(&question) {
+            println!("Nothing was purged.");
+            return Ok(());
+        }
+    }
+
+    let mut count = 0;
+    for journal in selected_journals(config, args)?.iter() {
+        count += config.trash(journal).empty(older_than)?;
+    }
+    println!("{} entries purged.", count);
+    Ok(())
+}
+
+// Back to the journal it was removed from, or the selected one if that journal is gone
+fn restore_entry(config: &Config, args: &Args) -> Result<(), String> {
+    let journal = get_journal(config, &args.flag_journal)?;
+    let trash = config.trash(journal);
+    let trashed = unique_by_prefix(trash.entries()?, &args.arg_id, |t| t.entry().id(),
+                                   &format!("the trash of {}", journal.name()))?;
+
+    let target = config.journals.iter().find(|j| j.name() == trashed.journal()).unwrap_or(journal);
+    let entry = trashed.entry().clone();
+
+    let id = target.upsert_entry(entry.clone())?;
+    trash.remove(&vec![*entry.id()])?;
+    let entry = saved_as(entry, id);
+    println!("Restored to {}:", target.name());
+    println!("{}", output::entry_line(trashed.entry(), output::terminal_width()));
+    config.record(target, &format!("Restore entry {} from the trash", describe(&entry)),
+           vec![Change::new(target.name(), None, Some(entry))])
+}
+
+
+// Reverts the last operation on the journal, repeating it goes further back
+fn undo(config: &Config, journal: &Box<dyn Journal>) -> Result<(), String> {
+    match config.undo(journal)? {
+        Some(operation) => println!("Undone: {}", operation.description()),
+        None => println!("Nothing to undo in {}.", journal.name())
+    }
+    Ok(())
+}
+
+fn redo(config: &Config, journal: &Box<dyn Journal>) -> Result<(), String> {
+    match config.redo(journal)? {
+        Some(operation) => println!("Redone: {}", operation.description()),
+        None => println!("Nothing to redo in {}.", journal.name())
+    }
+    Ok(())
 }
 
+
+
 pub fn process_args(args: &Args) -> Result<(), String> {
     let mut config: Config = Config::load().unwrap();
 
     if args.flag_version { return handle_version(); }
     if args.flag_add { return handle_add_journal(&mut config); }
+    if args.cmd_journal { return journal_command(&mut config, args); }
+
+    if args.cmd_list { return list_entries(&config, args); }
+    if args.cmd_search { return search_entries(&config, args); }
+    if args.cmd_tags { return list_tags(&config, args); }
+    if args.cmd_tag { return tag_command(&config, args); }
+    if args.cmd_rm { return remove_entries(&config, args); }
+    if args.cmd_trash && args.cmd_empty { return empty_trash(&config, args); }
+    if args.cmd_trash { return list_trash(&config, args); }
+    if args.cmd_restore { return restore_entry(&config, args); }
+
+    if args.cmd_edit { return edit_entry(&config, get_journal(&config, &args.flag_journal)?, args); }
+    if args.cmd_history { return entry_history(&config, get_journal(&config, &args.flag_journal)?, args); }
+    if args.flag_undo { return undo(&config, get_journal(&config, &args.flag_journal)?); }
+    if args.flag_redo { return redo(&config, get_journal(&config, &args.flag_journal)?); }
+    if args.flag_compact { return get_journal(&config, &args.flag_journal)?.compact(); }
+
+    // The first word can pick the journal, so the default one isn't needed yet
+    let (journal, content) = route_entry(&config, args)?;
+    add_new_entry(&config, journal, &content, args)
+}
+
+#[cfg(test)]
+mod tests {
+    use rjrn::config::Config;
+    use rjrn::journal::JournalRegistry;
+    use rjrn::memory_journal::MemoryJournalFactory;
+    use cli_args;
+    use rjrn::entry::EntryBuilder;
+    use rjrn::query::Query;
+    use chrono::{Duration, Utc};
+    use std::env;
+    use uuid::Uuid;
+    use super::{change_entry_tag, compose_in_editor, edited_entry, entry_to_front_matter, listed_entries, remove_entries,
+                route_entry, search_entries};
+
+    fn config(text: &str) -> Config {
+        let mut registry = JournalRegistry::with_builtins();
+        registry.register(Box::new(MemoryJournalFactory));
+        Config::from_str_with_registry(text, registry).unwrap()
+    }
+
+    fn parsed(line: &str) -> cli_args::Args {
+        cli_args::parse(line.split(' ').map(|w| w.to_string()).collect()).unwrap()
+    }
+
+    fn route(config: &Config, line: &str) -> Result<(String, Vec<String>), String> {
+        route_entry(config, &parsed(line)).map(|(journal, content)| (journal.name().to_string(), content))
+    }
+
+    #[test]
+    fn first_word_routes_without_a_default_journal() {
+        let config = config(r#"{"journals": [{"type": "MemoryJournal", "name": "notes"},
+                                             {"type": "MemoryJournal", "name": "work"}]}"#);
+        assert_eq!(route(&config, "rjrn work fixed the build").unwrap(),
+                   ("work".to_string(), vec!["fixed the build".to_string()]));
+        assert!(route(&config, "rjrn fixed the build").is_err());
+    }
+
+    #[test]
+    fn unrouted_entries_go_to_the_default_journal() {
+        let config = config(r#"{"journals": [{"type": "MemoryJournal", "name": "notes", "default": true},
+                                             {"type": "MemoryJournal", "name": "work"}]}"#);
+        assert_eq!(route(&config, "rjrn fixed the build").unwrap().0, "notes");
+        assert_eq!(route(&config, "rjrn --no-route work is done").unwrap(),
+                   ("notes".to_string(), vec!["work".to_string(), "is".to_string(), "done".to_string()]));
+        assert_eq!(route(&config, "rjrn --journal notes -- work is done").unwrap().0, "notes");
+    }
+
+    #[test]
+    fn edited_entries_get_the_tags_written_in_them() {
+        let mut builder = EntryBuilder::new();
+        builder.content("Fixed the build #work".to_string()).tag("ci");
+        let entry = builder.finalize().unwrap();
+        assert_eq!(entry.tags(), &vec!["work", "ci"]);
+
+        let text = entry_to_front_matter(&entry).replace("Fixed the build #work", "Fixed the build with @ana #Deploy");
+        let edited = edited_entry(&entry, &text).unwrap();
+        assert_eq!(edited.id(), entry.id());
+        assert_eq!(edited.content(), "Fixed the build with @ana #Deploy");
+        assert_eq!(edited.tags(), &vec!["work", "ci", "ana", "deploy"]);
 
-    let journal = try!(get_journal(&config, &args.flag_journal));
-    if args.flag_undo { return undo_last_entry(&journal); }
+        let untagged = entry_to_front_matter(&entry).replace("[work, ci]", "[]").replace(" #work", "");
+        assert_eq!(edited_entry(&entry, &untagged).unwrap().tags(), &Vec::<String>::new());
+    }
+
+    #[test]
+    fn tags_are_added_to_and_removed_from_entries() {
+        let config = config(r#"{"journals": [{"type": "MemoryJournal", "name": "notes", "default": true}]}"#);
+        let journal = &config.journals[0];
+        let id = journal.upsert_entry(EntryBuilder::new().content("Fixed the build".to_string())
+                                      .finalize().unwrap()).unwrap();
+        let prefix = &id.simple().to_string()[..8];
+        let last = || config.operation_log(journal).last_done().unwrap().unwrap().description().to_string();
+
+        change_entry_tag(&config, journal, &parsed(&format!("rjrn tag add {} #Work", prefix))).unwrap();
+        assert_eq!(journal.entries().unwrap()[0].tags(), &vec!["work"]);
+        assert!(last().starts_with("Add tag work to entry"));
+
+        change_entry_tag(&config, journal, &parsed(&format!("rjrn tag remove {} work", prefix))).unwrap();
+        assert!(journal.entries().unwrap()[0].tags().is_empty());
+        assert!(last().starts_with("Remove tag work from entry"), "{}", last());
+    }
 
-    add_new_entry(&journal, args)
+    #[test]
+    fn list_shows_the_last_entries_oldest_first() {
+        let config = config(r#"{"journals": [{"type": "MemoryJournal", "name": "notes", "default": true}]}"#);
+        let journal = &config.journals[0];
+        for (days, content) in [(1, "Newest"), (3, "Oldest #work"), (2, "Middle #work")].iter() {
+            let mut builder = EntryBuilder::new();
+            builder.content(content.to_string())
+                .created_at(Utc::now() - Duration::days(*days))
+                .starred(*days == 3);
+            journal.upsert_entry(builder.finalize().unwrap()).unwrap();
+        }
+
+        let listed = |line: &str, query: &str| -> Vec<String> {
+            listed_entries(journal, &Query::parse(query).unwrap(), &parsed(line)).unwrap()
+                .iter().map(|e| e.content().clone()).collect()
+        };
+        assert_eq!(listed("rjrn list", ""), ["Oldest #work", "Middle #work", "Newest"]);
+        assert_eq!(listed("rjrn list --count=2", ""), ["Middle #work", "Newest"]);
+        assert_eq!(listed("rjrn list --count=1", "tag:work"), ["Middle #work"]);
+        assert_eq!(listed("rjrn list --starred", ""), ["Oldest #work"]);
+    }
+
+    #[test]
+    fn search_fails_when_nothing_matches() {
+        let config = config(r#"{"journals": [{"type": "MemoryJournal", "name": "notes", "default": true},
+                                             {"type": "MemoryJournal", "name": "work"}]}"#);
+        config.journals[1].upsert_entry(EntryBuilder::new().content("Deploy went fine".to_string())
+                                        .finalize().unwrap()).unwrap();
+
+        assert!(search_entries(&config, &parsed("rjrn search deploy")).unwrap_err().contains("No entries match"));
+        assert!(search_entries(&config, &parsed("rjrn search deploy --all")).is_ok());
+        assert!(search_entries(&config, &parsed("rjrn search De.loy --journal=work --regex")).is_ok());
+        assert!(search_entries(&config, &parsed("rjrn search deploy --all --regex")).is_err());
+        assert!(search_entries(&config, &parsed("rjrn search dep --all --word")).is_err());
+    }
+
+    // The only test running an editor, `$VISUAL` is shared by the whole process
+    #[test]
+    fn composed_entries_are_read_from_the_editor() {
+        let compose = |editor: &str| {
+            env::set_var("VISUAL", editor);
+            let mut builder = EntryBuilder::new();
+            compose_in_editor(&mut builder, &parsed("rjrn --tag=#Home --star"))
+                .map(|saved| if saved { Some(builder.finalize().unwrap()) } else { None })
+        };
+
+        let entry = compose("f() { sed -i 's/^title: null$/title: Groceries/' \"$1\" && \
+                                        printf 'Milk and #eggs\\n' >> \"$1\"; }; f").unwrap().unwrap();
+        assert_eq!(entry.title(), &Some("Groceries".to_string()));
+        assert_eq!(entry.content(), "Milk and #eggs");
+        assert_eq!(entry.tags(), &vec!["home", "eggs"]);
+        assert!(entry.starred());
+
+        assert!(compose("true").unwrap().is_none());
+        assert!(compose("false").unwrap_err().contains("nothing was saved"));
+        env::remove_var("VISUAL");
+    }
+
+    #[test]
+    fn rm_takes_unique_id_prefixes_or_a_query() {
+        let config = config(r#"{"journals": [{"type": "MemoryJournal", "name": "notes", "default": true},
+                                             {"type": "MemoryJournal", "name": "work"}]}"#);
+        for (journal, id, content) in [(0, "a1b2c3d4-0000-4000-8000-000000000001", "Milk"),
+                                       (0, "a1b2ffff-0000-4000-8000-000000000002", "Bread #home"),
+                                       (1, "b0000000-0000-4000-8000-000000000003", "Deploy #home")].iter() {
+            let mut builder = EntryBuilder::new();
+            builder.id(Uuid::parse_str(id).unwrap()).content(content.to_string());
+            config.journals[*journal].upsert_entry(builder.finalize().unwrap()).unwrap();
+        }
+        let rm = |line: &str| remove_entries(&config, &parsed(line));
+        let count = |journal: usize| config.journals[journal].entries().unwrap().len();
+
+        assert!(rm("rjrn rm a1b2 --yes").unwrap_err().contains("2 entries have an id starting with a1b2"));
+        assert!(rm("rjrn rm b000 --yes").unwrap_err().contains("No entry"));
+        rm("rjrn rm a1b2-c3 a1b2c3d4 --dry-run").unwrap();
+        assert_eq!(count(0), 2);
+
+        rm("rjrn rm a1b2-c3 a1b2c3d4 --yes").unwrap();
+        assert_eq!(count(0), 1);
+        assert_eq!(config.trash(&config.journals[0]).entries().unwrap().len(), 1);
+
+        rm("rjrn rm --where=tag:home --all --yes").unwrap();
+        assert_eq!((count(0), count(1)), (0, 0));
+        assert!(rm("rjrn rm --where=tag:home --all --yes").unwrap_err().contains("No entries match"));
+    }
 }
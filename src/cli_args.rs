@@ -1,21 +1,56 @@
 extern crate docopt;
 
 use self::docopt::Docopt;
+use std::env;
 
-const USAGE: &'static str ="
+const USAGE: &str ="
 Journal.
 
 The config will be kept at: `~/.rjrn.config`.
+Without content, the entry is written in `$VISUAL` or `$EDITOR`, or read from stdin when it's piped.
 If the first word matches some journal name, the entry will be created in that journal, otherwise it goes to the default journal.
+The word is dropped from the entry, `--no-route` or `--journal` keeps it. Other words for a journal can be set in `aliases` of the config.
+list, search, edit, tags, tag, rm, trash, restore, history and journal are commands as the first word, put `--` before the content to write them, e.g. `rjrn -- list milk eggs`.
+
+Entries can be filtered with a query, e.g. `tag:work starred:true after:2026-01-01 before:yesterday deploy`.
+Terms: tag:, starred:, after:, before:, updated_after:, updated_before:, title:, journal:, id: and text, `-` negates a term.
+
+`tag merge a b c` merges the tags a and b into c.
+
+`journal add` without `--type` asks for the journal interactively. Types: file, encrypted-file, jsonl, sqlite, markdown-dir, jrnl, trello, git.
+Other settings are given as `--option key=value`: passphrase_command for encrypted-file, key, token and list_id for trello, repository and journal (the type kept in it) for git.
+`--option key=-` reads the value from stdin. Trello key and token are also taken from $RJRN_TRELLO_KEY and $RJRN_TRELLO_TOKEN.
 
 Usage:
-  rjrn [--verbose]
+  rjrn [--title=<title>] [--star] [--tag=<tag>...] [--verbose] [--journal=<journal>]
+  rjrn list [<query>...] [--count=<n>] [--full] [--starred] [--all] [--journal=<journal>] [--verbose]
+  rjrn search <pattern> [--word | --regex] [--all] [--journal=<journal>] [--verbose]
+  rjrn edit <id> [--journal=<journal>] [--verbose]
+  rjrn tags [--all] [--journal=<journal>] [--verbose]
+  rjrn tag rename <old> <new> [--all] [--journal=<journal>] [--verbose]
+  rjrn tag merge <tags>... [--all] [--journal=<journal>] [--verbose]
+  rjrn tag (add | remove) <id> <tag> [--journal=<journal>] [--verbose]
+  rjrn rm <ids>... [--yes] [--dry-run] [--journal=<journal>] [--verbose]
+  rjrn rm --where=<query> [--yes] [--dry-run] [--all] [--journal=<journal>] [--verbose]
+  rjrn trash empty [--older-than=<age>] [--yes] [--all] [--journal=<journal>] [--verbose]
+  rjrn trash [--all] [--journal=<journal>] [--verbose]
+  rjrn restore <id> [--journal=<journal>] [--verbose]
+  rjrn journal add [--type=<type>] [--name=<name>] [--path=<path>] [--option=<option>...] [--default] [--verbose]
+  rjrn journal list [--verbose]
+  rjrn journal show <name> [--verbose]
+  rjrn journal rename <old> <new> [--verbose]
+  rjrn journal remove <name> [--delete-file] [--yes] [--verbose]
+  rjrn journal set-default <name> [--verbose]
+  rjrn journal move-path <name> <path> [--move-file] [--verbose]
+  rjrn history <id> [--full] [--restore=<n>] [--journal=<journal>] [--verbose]
   rjrn <content>... [--verbose]
   rjrn <content>... [--verbose]
-  rjrn <content>... [--title=<title>] [--star] [--verbose] [--journal=<journal>]
+  rjrn <content>... [--title=<title>] [--star] [--tag=<tag>...] [--no-route] [--verbose] [--journal=<journal>]
   rjrn (--help | -h)
   rjrn (--version | -v)
-  rjrn --undo [--verbose]
+  rjrn --undo [--journal=<journal>] [--verbose]
+  rjrn --redo [--journal=<journal>] [--verbose]
+  rjrn --compact [--verbose] [--journal=<journal>]
   rjrn --add [--verbose]
 
 Options:
@@ -24,25 +59,191 @@ Options:
   --title TITLE              The title of the new entry
   --journal JOURNAL          Name of the journal, if empty it selects default journal
   --star                     Marks the entry as favourite
+  --tag TAG                  Adds a tag to the entry, can be repeated. #tags and @mentions in the text are added too
+  --no-route                 Keeps the entry in the default journal even if the first word is a journal name
   --add                      If you'd like to add a new journal file
-  --undo                     Deletes last entry
+  --undo                     Reverts the last change to the journal, can be repeated
+  --redo                     Applies again the last undone change
+  --compact                  Drops old revisions from the journal file
+  --count N                  Number of entries to list [default: 10]
+  --full                     Shows the whole content of entries
+  --starred                  Lists only starred entries
+  --all                      Works on all journals instead of one
+  --where QUERY              Removes the entries matching the query instead of ids
+  --yes                      Removes without asking for confirmation
+  --dry-run                  Only lists what would be removed
+  --older-than AGE           Purges only entries removed before, like 30d, 12h or 2w
+  --type TYPE                Type of the new journal, like file or markdown-dir
+  --name NAME                Name of the new journal
+  --path PATH                File or directory of the new journal
+  --option OPTION            Setting of the new journal as key=value, can be repeated
+  --default                  Makes the new journal the default one
+  --delete-file              Deletes the file of the removed journal too
+  --move-file                Moves the file of the journal to the new path too
+  --restore N                Saves revision N of the entry as its current version
+  --word                     Matches the search pattern as whole words only
+  --regex                    The search pattern is a regular expression
   --verbose                  Print debug statements
 ";
-#[derive(Debug, RustcDecodable)]
+#[derive(Debug, Deserialize)]
 pub struct Args {
     pub flag_version: bool,
-    pub flag_help: bool,
     pub flag_star: bool,
+    pub flag_tag: Vec<String>,
     pub flag_add: bool,
     pub flag_title: String,
     pub flag_journal: String,
+    pub flag_no_route: bool,
     pub flag_undo: bool,
+    pub flag_redo: bool,
+    pub flag_compact: bool,
     pub flag_verbose: bool,
+    pub flag_count: usize,
+    pub flag_full: bool,
+    pub flag_starred: bool,
+    pub flag_all: bool,
+    pub flag_word: bool,
+    pub flag_regex: bool,
+    pub cmd_list: bool,
+    pub cmd_search: bool,
+    pub cmd_edit: bool,
+    pub cmd_history: bool,
+    pub flag_restore: String,
+    pub arg_id: String,
+    pub cmd_tags: bool,
+    pub cmd_tag: bool,
+    pub cmd_rename: bool,
+    pub cmd_merge: bool,
+    pub cmd_add: bool,
+    pub cmd_remove: bool,
+    pub arg_old: String,
+    pub arg_new: String,
+    pub arg_tags: Vec<String>,
+    pub arg_tag: String,
+    pub cmd_rm: bool,
+    pub arg_ids: Vec<String>,
+    pub flag_where: String,
+    pub flag_yes: bool,
+    pub flag_dry_run: bool,
+    pub cmd_trash: bool,
+    pub cmd_empty: bool,
+    pub cmd_restore: bool,
+    pub flag_older_than: String,
+    pub cmd_journal: bool,
+    pub cmd_show: bool,
+    pub cmd_set_default: bool,
+    pub cmd_move_path: bool,
+    pub arg_name: String,
+    pub arg_path: String,
+    pub flag_delete_file: bool,
+    pub flag_move_file: bool,
+    pub flag_type: String,
+    pub flag_name: String,
+    pub flag_path: String,
+    pub flag_option: Vec<String>,
+    pub flag_default: bool,
+    pub arg_pattern: String,
+    pub arg_query: Vec<String>,
     pub arg_content: Vec<String>,
 }
 
 pub fn get() -> Args {
-    Docopt::new(USAGE)
-        .and_then(|d| d.decode())
-        .unwrap_or_else(|e| e.exit())
+    parse(env::args().collect()).unwrap_or_else(|e| e.exit())
+}
+
+pub fn parse(mut argv: Vec<String>) -> Result<Args, docopt::Error> {
+    let content = escaped_content(&mut argv);
+    let mut args: Args = Docopt::new(USAGE).and_then(|d| d.argv(argv).deserialize())?;
+
+    if let Some(content) = content {
+        args.arg_content = content;
+    }
+    Ok(args)
+}
+
+// Docopt matches commands after `--` too, so `rjrn -- list milk` would list.
+// When only options and their values come before `--`, the words after it
+// are taken out and parsed as a single placeholder word, then put back as
+// the content.
+fn escaped_content(argv: &mut Vec<String>) -> Option<Vec<String>> {
+    let split = match argv.iter().skip(1).position(|arg| arg == "--") {
+        Some(n) => n + 1,
+        None => return None
+    };
+    if split + 1 == argv.len() {
+        return None;
+    }
+
+    let mut options = argv[1..split].iter();
+    while let Some(arg) = options.next() {
+        if !arg.starts_with('-') {
+            return None;
+        }
+        if takes_value(arg) {
+            options.next();
+        }
+    }
+
+    let content = argv.split_off(split + 1);
+    argv.push("content".to_string());
+    Some(content)
+}
+
+// `--journal work`, options shown with a value in the Options above.
+// `--journal=work` is a single word.
+fn takes_value(arg: &str) -> bool {
+    USAGE.lines()
+        .map(|line| line.trim_start())
+        .filter(|line| line.starts_with("--"))
+        .any(|line| {
+            let mut words = line.split_whitespace();
+            words.next() == Some(arg) &&
+                words.next().map(|w| w.chars().all(|c| c.is_ascii_uppercase())).unwrap_or(false)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Args};
+
+    fn parsed(line: &str) -> Args {
+        parse(line.split(' ').map(|w| w.to_string()).collect()).unwrap()
+    }
+
+    #[test]
+    fn words_after_double_dash_are_content() {
+        let args = parsed("rjrn -- list milk eggs");
+        assert!(!args.cmd_list);
+        assert_eq!(args.arg_content, vec!["list", "milk", "eggs"]);
+    }
+
+    #[test]
+    fn option_values_before_double_dash_are_skipped() {
+        let args = parsed("rjrn --journal work --tag shop -- list milk");
+        assert!(!args.cmd_list);
+        assert_eq!(args.flag_journal, "work");
+        assert_eq!(args.flag_tag, vec!["shop"]);
+        assert_eq!(args.arg_content, vec!["list", "milk"]);
+
+        let args = parsed("rjrn --journal=work --star -- rm the weeds");
+        assert!(!args.cmd_rm);
+        assert!(args.flag_star);
+        assert_eq!(args.arg_content, vec!["rm", "the", "weeds"]);
+    }
+
+    #[test]
+    fn commands_before_double_dash_stay_commands() {
+        let args = parsed("rjrn list -- milk");
+        assert!(args.cmd_list);
+        assert_eq!(args.arg_query, vec!["milk"]);
+    }
+
+    #[test]
+    fn flags_are_not_taken_for_options_with_values() {
+        assert!(super::takes_value("--journal"));
+        assert!(super::takes_value("--count"));
+        assert!(!super::takes_value("--star"));
+        assert!(!super::takes_value("--help"));
+        assert!(!super::takes_value("work"));
+    }
 }
@@ -1,5 +1,6 @@
-//! File Journal which implements handlers for a journal that's on the file disk.
-//! It's the default and only handler right now.
+//! Config keeps the list of journals and the registry of journal backends
+//! used to read them back. It's read from `~/.rjrn.config` by `Config::load`,
+//! from any other file with `Config::load_from`, or from a string.
 //!
 //! Usage for bootstraping and saving:
 //!
@@ -9,23 +10,48 @@
 //!    use rjrn::file_journal::FileJournal;
 //!
 //!    let journal = FileJournal::new("name", "path");
-//!    let config = Config { file_journals: vec![Box::new(journal)]};
+//!    let config = Config::new(vec![Box::new(journal)]);
 //!    assert_eq!(config, Config::from_json(config.to_json()));
 //!```
+//!
+//! Without touching the disk:
+//!
+//!```
+//!    use rjrn::config::Config;
+//!    use rjrn::journal::JournalRegistry;
+//!    use rjrn::memory_journal::MemoryJournalFactory;
+//!
+//!    let mut registry = JournalRegistry::with_builtins();
+//!    registry.register(Box::new(MemoryJournalFactory));
+//!
+//!    let text = r#"{"journals": [{"type": "MemoryJournal", "name": "notes", "default": true}],
+//!                   "aliases": {"notes": ["n"]}}"#;
+//!    let config = Config::from_str_with_registry(text, registry).unwrap();
+//!    assert!(config.path().is_none());
+//!    assert_eq!(config.journal_with_name_or_default("").unwrap().name(), "notes");
+//!    assert_eq!(config.route("n:").unwrap().name(), "notes");
+//!```
 
-use journal::Journal;
-use file_journal::FileJournal;
+use journal::{file_with_siblings, Journal, JournalOptions, JournalRegistry};
+use atomic_write;
+use file_lock::{self, FileLock};
+use entry::{saved_as, Entry};
+use trash::Trash;
+use operation_log::{Change, Operation, OperationLog};
+use store::Store;
 
-use rustc_serialize::json::{self, Json};
+use serde_json::{self, Map, Value};
+use std::cell::RefCell;
 use std::collections::BTreeMap;
-use std::error::Error;
 use std::io::prelude::*;
-use std::io;
-use std::fs::{File, OpenOptions};
-use std::path::{PathBuf};
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
 use std::env;
 use std::cmp::PartialEq;
-const CONFIG_PATH: &'static str = ".rjrn.config";
+use std::str::FromStr;
+use std::time::Duration;
+use uuid::Uuid;
+const CONFIG_PATH: &str = ".rjrn.config";
 
 
 // FIXME: memoize the result
@@ -36,75 +62,292 @@ fn config_path() -> PathBuf {
     }
 }
 
-// FIXME: How to base that on the trait instead of specific type?
 #[derive(Debug)]
 pub struct Config {
-    pub file_journals: Vec<Box<FileJournal>>
+    pub journals: Vec<Box<dyn Journal>>,
+    // Journals with a type that isn't registered, kept so saving doesn't drop them
+    unknown_journals: Vec<Value>,
+    registry: JournalRegistry,
+    // File the config was loaded from and is saved to, none when it's only in memory
+    path: Option<PathBuf>,
+    // How long `save` waits for another rjrn saving the same config
+    lock_timeout_ms: u64,
+    // Other first words routing new entries to a journal, by journal name
+    aliases: BTreeMap<String, Vec<String>>,
+    // Trash and undo history of journals when there is no config file, by `<kind>/<journal>`
+    memory: RefCell<BTreeMap<String, Store>>,
 }
 
 impl Config {
-    pub fn to_json(&self) -> Json {
-        let mut d: BTreeMap<String, Json> = BTreeMap::new();
-        d.insert("journals".to_string(), Json::Array(self.file_journals.iter().map(|j| {
+    pub fn new(journals: Vec<Box<dyn Journal>>) -> Config {
+        Config {
+            journals,
+            unknown_journals: vec![],
+            registry: JournalRegistry::with_builtins(),
+            path: None,
+            lock_timeout_ms: file_lock::DEFAULT_TIMEOUT_MS,
+            aliases: BTreeMap::new(),
+            memory: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn with_registry(registry: JournalRegistry) -> Config {
+        Config {
+            journals: vec![],
+            unknown_journals: vec![],
+            registry,
+            path: None,
+            lock_timeout_ms: file_lock::DEFAULT_TIMEOUT_MS,
+            aliases: BTreeMap::new(),
+            memory: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn registry(&self) -> &JournalRegistry {
+        &self.registry
+    }
+
+    pub fn path(&self) -> Option<&PathBuf> {
+        self.path.as_ref()
+    }
+
+    // Trash of the journal, in a directory next to the config file
+    pub fn trash<'a>(&self, journal: &'a Box<dyn Journal>) -> Trash<'a> {
+        Trash::with_store(self.store("trash", journal.name()), journal)
+    }
+
+    // Undo and redo history of the journal, next to the trash
+    pub fn operation_log<'a>(&self, journal: &'a Box<dyn Journal>) -> OperationLog<'a> {
+        OperationLog::with_store(self.store("undo", journal.name()), journal)
+    }
+
+    // Memory for configs without a file, kept as long as the config
+    fn store(&self, kind: &str, journal: &str) -> Store {
+        match self.journal_file(kind, journal) {
+            Some(path) => {
+                self.move_legacy_file(journal, &path);
+                Store::file(&path, self.lock_timeout_ms)
+            },
+            None => self.memory.borrow_mut()
+                    .entry(format!("{}/{}", kind, journal))
+                    .or_insert_with(Store::memory)
+                    .clone()
+        }
+    }
+
+    // `~/.rjrn.<kind>/<journal>.json`, none without a config file
+    fn journal_file(&self, kind: &str, journal: &str) -> Option<PathBuf> {
+        self.path.as_ref().map(|path| path.with_extension(kind).join(format!("{}.json", file_name(journal))))
+    }
+
+    // Files were named with every other character replaced by `_` before, so
+    // `my notes` and `my_notes` shared them. Such a file is only taken over by
+    // the one journal it can belong to, ignoring case like some file systems.
+    fn move_legacy_file(&self, journal: &str, path: &Path) {
+        let legacy_name = legacy_file_name(journal);
+        if legacy_name == file_name(journal) || path.exists() {
+            return;
+        }
+
+        let legacy = path.with_file_name(format!("{}.json", legacy_name));
+        let owners = self.journals.iter()
+            .filter(|j| legacy_file_name(j.name()).to_lowercase() == legacy_name.to_lowercase())
+            .count();
+        if !legacy.exists() || owners > 1 {
+            return;
+        }
+
+        debug!("Moving {} of {} to {}", legacy.display(), journal, path.display());
+        if let Err(why) = fs::rename(&legacy, path) {
+            warn!("Couldn't move {} to {} because: {}", legacy.display(), path.display(), why);
+        }
+    }
+
+    // Saves the operation so `undo` can revert it, operations without changes are skipped
+    pub fn record(&self, journal: &Box<dyn Journal>, description: &str, changes: Vec<Change>) -> Result<(), String> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        self.operation_log(journal).record(Operation::new(description, changes))
+    }
+
+    // Entries are put in the trash first, so a failing removal doesn't lose them
+    pub fn trash_entries(&self, journal: &Box<dyn Journal>, entries: Vec<Box<Entry>>) -> Result<(), String> {
+        let ids: Vec<Uuid> = entries.iter().map(|e| *e.id()).collect();
+        self.trash(journal).put(journal.name(), entries)?;
+        journal.remove_entries(&ids)
+    }
+
+    // Reverts the last operation on the journal and returns it, repeating it
+    // goes further back. None when there's nothing to undo.
+    pub fn undo(&self, journal: &Box<dyn Journal>) -> Result<Option<Operation>, String> {
+        let log = self.operation_log(journal);
+        let operation = match log.last_done()? {
+            Some(operation) => operation,
+            None => return Ok(None)
+        };
+
+        // Entries saved again can get new ids, the log keeps the ones they have now
+        let mut changes = vec![];
+        for change in operation.changes().iter().rev() {
+            let before = self.apply_change(journal, change.journal(), change.after(), change.before())?;
+            changes.insert(0, Change::new(change.journal(), before, change.after().clone()));
+        }
+
+        log.mark_undone(operation.with_changes(changes))?;
+        Ok(Some(operation))
+    }
+
+    // Applies again the last undone operation and returns it
+    pub fn redo(&self, journal: &Box<dyn Journal>) -> Result<Option<Operation>, String> {
+        let log = self.operation_log(journal);
+        let operation = match log.last_undone()? {
+            Some(operation) => operation,
+            None => return Ok(None)
+        };
+
+        let mut changes = vec![];
+        for change in operation.changes().iter() {
+            let after = self.apply_change(journal, change.journal(), change.before(), change.after())?;
+            changes.push(Change::new(change.journal(), change.before().clone(), after));
+        }
+
+        log.mark_redone(operation.with_changes(changes))?;
+        Ok(Some(operation))
+    }
+
+    // Takes the entry from `from` to `to`, going through the trash when it has to
+    // be removed or comes back, and returns `to` as it was saved. Changes of a
+    // renamed journal apply to the journal the log belongs to.
+    fn apply_change(&self, journal: &Box<dyn Journal>, name: &str,
+                    from: &Option<Entry>, to: &Option<Entry>) -> Result<Option<Entry>, String> {
+        let journal = self.journals.iter().find(|j| j.name() == name).unwrap_or(journal);
+
+        match (from, to) {
+            (_, Some(entry)) => {
+                let id = journal.upsert_entry(entry.clone())?;
+                if from.is_none() {
+                    self.trash(journal).remove(&vec![*entry.id()])?;
+                }
+                Ok(Some(saved_as(entry.clone(), id)))
+            },
+            (Some(entry), &None) => {
+                match journal.entries()?.into_iter().find(|e| e.id() == entry.id()) {
+                    Some(current) => {
+                        self.trash_entries(journal, vec![current])?;
+                        Ok(None)
+                    },
+                    None => Err(format!("Entry {} isn't in {} anymore, it was changed outside of rjrn",
+                                        entry.id(), journal.name()))
+                }
+            },
+            (&None, &None) => Ok(None)
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut d = Map::new();
+        let mut journals: Vec<Value> = self.journals.iter().map(|j| {
             j.to_json()
+        }).collect();
+        journals.extend(self.unknown_journals.iter().cloned());
+
+        d.insert("journals".to_string(), Value::Array(journals));
+        d.insert("lock_timeout_ms".to_string(), Value::from(self.lock_timeout_ms));
+        d.insert("aliases".to_string(), Value::Object(self.aliases.iter().map(|(name, aliases)| {
+            (name.clone(), Value::Array(aliases.iter().map(|a| Value::String(a.clone())).collect()))
         }).collect()));
-        Json::Object(d)
+        Value::Object(d)
     }
 
-    pub fn from_json(config: Json) -> Config {
+    pub fn from_json(config: Value) -> Config {
+        Config::from_json_with_registry(config, JournalRegistry::with_builtins())
+    }
+
+    pub fn from_json_with_registry(config: Value, registry: JournalRegistry) -> Config {
         debug!("Read config: {:?}", config);
-        let ref journals = config["journals"].as_array().unwrap();
-        let file_journals = journals.iter()
-            .filter(|j| j.find("type").unwrap().as_string().unwrap() == "FileJournal")
-            .map(|j| Box::new(FileJournal::from_json(j))).collect();
+        let mut result = Config::with_registry(registry);
+        if let Some(timeout) = config.get("lock_timeout_ms").and_then(|t| t.as_u64()) {
+            result.lock_timeout_ms = timeout;
+        }
+        if let Some(aliases) = config.get("aliases").and_then(|a| a.as_object()) {
+            for (name, list) in aliases.iter() {
+                let list: Vec<String> = list.as_array().map(|l| {
+                    l.iter().filter_map(|a| a.as_str()).map(|a| a.to_string()).collect()
+                }).unwrap_or(vec![]);
+                result.aliases.insert(name.clone(), list);
+            }
+        }
+        let journals = config["journals"].as_array().unwrap();
 
-       Config {
-            file_journals: file_journals
+        for j in journals.iter() {
+            match result.registry.from_json(j) {
+                Ok(journal) => result.journals.push(journal),
+                Err(why) => {
+                    warn!("Skipping journal: {}", why);
+                    result.unknown_journals.push(j.clone());
+                }
+            }
         }
+
+        result
     }
 
-    fn config_file() -> File {
-        let get_file = || { OpenOptions::new().read(true).write(true).clone() };
+    // An empty string is a config without journals
+    pub fn from_str_with_registry(s: &str, registry: JournalRegistry) -> Result<Config, String> {
+        if s.trim().is_empty() {
+            return Ok(Config::with_registry(registry));
+        }
 
-        match get_file().open(config_path()) {
-            Err(_) =>  {
-                debug!("Creating new config file...");
-                match get_file().create(true).truncate(true).open(config_path()) {
-                    Ok(file) => file,
-                    Err(why) =>
-                        panic!("Couldn't create config file because: {}",
-                               Error::description(&why))
+        match serde_json::from_str::<Value>(s) {
+            Ok(config) => {
+                if config.get("journals").and_then(|j| j.as_array()).is_none() {
+                    return Err("Config is missing its list of journals".to_string());
                 }
+                Ok(Config::from_json_with_registry(config, registry))
             },
-            Ok(file) => file,
+            Err(why) => Err(format!("Couldn't parse the config: {}", why))
         }
     }
 
     pub fn load() -> Result<Config, String> {
-        let mut file = Config::config_file();
+        Config::load_with_registry(JournalRegistry::with_builtins())
+    }
+
+    pub fn load_with_registry(registry: JournalRegistry) -> Result<Config, String> {
+        Config::load_from(&config_path(), registry)
+    }
+
+    // The file is created when it doesn't exist yet, `save` writes back to it
+    pub fn load_from(path: &Path, registry: JournalRegistry) -> Result<Config, String> {
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)
+                       .map_err(|why| format!("Couldn't open config file because: {}",
+                                              why))?;
         let mut s = String::new();
 
-        match file.read_to_string(&mut s) {
-            Err(why) => Err(format!("couldn't read {}",
-                               Error::description(&why))),
-            Ok(_) => {
-                match s.len() {
-                    0 => Ok(Config { file_journals: vec![] }),
-                    _ => Ok(Config::from_json(Json::from_str(&s).unwrap()))
+        file.read_to_string(&mut s)
+        .map_err(|why| format!("couldn't read {}", why))?;
 
-                }
-            }
-        }
+        let mut config = Config::from_str_with_registry(&s, registry)?;
+        config.path = Some(path.to_path_buf());
+        Ok(config)
     }
 
+    // Configs which weren't loaded from a file are kept in memory only
     pub fn save(&self) -> Result<(), String> {
-        let mut file  = Config::config_file();
+        let path = match self.path {
+            Some(ref path) => path,
+            None => {
+                debug!("Config isn't backed by a file, nothing to save");
+                return Ok(());
+            }
+        };
 
-        match writeln!(file, "{}", json::encode(&self.to_json()).unwrap()) {
-            Err(why) => {
-                Err(format!("Couldn't save the config file because: {}",
-                       Error::description(&why)))
-            },
+        let _lock = FileLock::acquire(path, Duration::from_millis(self.lock_timeout_ms))?;
+        let content = format!("{}\n", serde_json::to_string(&self.to_json()).unwrap());
+        match atomic_write::write(path, content.as_bytes()) {
+            Err(why) => Err(format!("Couldn't save the config file because: {}", why)),
             Ok(_) => {
                 info!("Config saved.");
                 Ok(())
@@ -113,37 +356,218 @@ impl Config {
     }
 
     pub fn add_journal(&mut self) -> Result<(), String> {
-        println!("What type of journal you'd like to add: ");
-        println!("(1) File Journal");
-        println!("(??) TODO: Dropbox Journal");
-        println!("(??) TODO: Trello Journal");
+        let mut journal = self.registry.bootstrap_cli()?;
+
+        if self.journals.is_empty() {
+            journal.set_default();
+        }
+
+        self.journals.push(journal);
+        Ok(())
+    }
 
-        let mut selected_option = String::new();
+    // Adds a journal without prompts, `kind` is the type or its short name like `file`
+    pub fn add_journal_with_options(&mut self, kind: &str, options: &JournalOptions,
+                                    default: bool) -> Result<(), String> {
+        if self.position(&options.name).is_ok() {
+            return Err(format!("There's already a journal named {}", options.name));
+        }
+
+        let journal = self.registry.from_options(kind, options)?;
+        self.journals.push(journal);
+
+        if default || self.journals.len() == 1 {
+            self.set_default_journal(&options.name)?;
+        }
+        Ok(())
+    }
+
+    // Journal a new entry starting with `word` goes to, by its name or one of
+    // its aliases. A colon after the word is allowed, like `work: fixed the build`.
+    pub fn route(&self, word: &str) -> Option<&Box<dyn Journal>> {
+        let word = word.trim_end_matches(':');
+        if word.is_empty() {
+            return None;
+        }
 
-        loop {
-            io::stdin().read_line(&mut selected_option)
-                .ok()
-                .expect("Failed to parse selection");
+        self.journals.iter().find(|j| j.name() == word)
+            .or_else(|| self.journals.iter().find(|j| self.aliases(j.name()).iter().any(|a| a == word)))
+    }
+
+    pub fn aliases(&self, journal: &str) -> &[String] {
+        self.aliases.get(journal).map(|a| a.as_slice()).unwrap_or(&[])
+    }
 
-            debug!("Selected option: {}", selected_option);
-            if selected_option.trim() == "1" {
-                let mut journal = try!(FileJournal::bootstrap_cli());
+    pub fn set_aliases(&mut self, journal: &str, aliases: Vec<String>) {
+        if aliases.is_empty() {
+            self.aliases.remove(journal);
+        } else {
+            self.aliases.insert(journal.to_string(), aliases);
+        }
+    }
+
+    // Path of the journal's file or directory, none for journals without one like Trello
+    pub fn journal_path(&self, name: &str) -> Result<Option<String>, String> {
+        let i = self.position(name)?;
+        let mut json = self.journals[i].to_json();
+        Ok(settings(&mut json)
+           .and_then(|d| d.get("path").and_then(|p| p.as_str()).map(|p| p.to_string())))
+    }
 
-                if self.file_journals.is_empty() {
-                    journal.set_default();
+    // Its trash and undo history follow it, the entries in the history keep the
+    // old name. The config is saved, if that fails the journal keeps its old
+    // name along with its trash and history.
+    pub fn rename_journal(&mut self, old: &str, new: &str) -> Result<(), String> {
+        let i = self.position(old)?;
+        if new.trim().is_empty() {
+            return Err("Please give the new name of the journal".to_string());
+        }
+        if self.position(new).is_ok() {
+            return Err(format!("There's already a journal named {}", new));
+        }
+
+        let previous = self.journals[i].to_json();
+        self.rebuild(i, |d| { d.insert("name".to_string(), Value::String(new.to_string())); })?;
+
+        let mut moved = vec![];
+        self.move_journal_state(old, new);
+        let result = self.move_journal_files(old, new, &mut moved).and_then(|_| self.save());
+
+        if result.is_err() {
+            for (from, to) in moved.iter().rev() {
+                if let Err(why) = fs::rename(to, from) {
+                    warn!("Couldn't move {} back to {} because: {}", to.display(), from.display(), why);
                 }
+            }
+            self.move_journal_state(new, old);
+            self.journals[i] = self.registry.from_json(&previous)?;
+        }
+        result
+    }
 
-                self.file_journals.push(Box::new(journal));
-                return Ok(())
-            } else {
-                println!("Failed to parse selection. Please try again:");
+    // Aliases and the trash and history kept in memory
+    fn move_journal_state(&mut self, from: &str, to: &str) {
+        if let Some(aliases) = self.aliases.remove(from) {
+            self.aliases.insert(to.to_string(), aliases);
+        }
+
+        let mut memory = self.memory.borrow_mut();
+        for kind in ["trash", "undo"].iter() {
+            if let Some(store) = memory.remove(&format!("{}/{}", kind, from)) {
+                memory.insert(format!("{}/{}", kind, to), store);
             }
         }
     }
 
-    pub fn file_journal_with_name_or_default(&self, name: &str) ->
-        Option<&Box<FileJournal>> {
-            self.file_journals
+    // Moved files are added to `moved` as they go, to move them back
+    fn move_journal_files(&self, old: &str, new: &str, moved: &mut Vec<(PathBuf, PathBuf)>) -> Result<(), String> {
+        for kind in ["trash", "undo"].iter() {
+            if let (Some(from), Some(to)) = (self.journal_file(kind, old), self.journal_file(kind, new)) {
+                self.move_legacy_file(old, &from);
+                if !from.exists() {
+                    continue;
+                }
+                if to.exists() {
+                    warn!("Keeping {}, {} already exists", from.display(), to.display());
+                    continue;
+                }
+                fs::rename(&from, &to)
+                .map_err(|why| format!("Couldn't move {} because: {}",
+                                       from.display(), why))?;
+                moved.push((from, to));
+            }
+        }
+
+        Ok(())
+    }
+
+    // The first journal left becomes the default if the default one is removed.
+    // The file of the journal is kept, its trash and undo history are deleted
+    // so a new journal with the same name starts without them.
+    pub fn remove_journal(&mut self, name: &str) -> Result<Box<dyn Journal>, String> {
+        let i = self.position(name)?;
+        for kind in ["trash", "undo"].iter() {
+            self.memory.borrow_mut().remove(&format!("{}/{}", kind, name));
+            if let Some(path) = self.journal_file(kind, name) {
+                self.move_legacy_file(name, &path);
+                // With its backups and lock
+                for file in file_with_siblings(&path.to_string_lossy(), &[]).iter() {
+                    fs::remove_file(file)
+                    .map_err(|why| format!("Couldn't delete {} because: {}",
+                                           file.display(), why))?;
+                }
+            }
+        }
+
+        let journal = self.journals.remove(i);
+        self.aliases.remove(name);
+
+        if journal.is_default() && !self.journals.is_empty() {
+            self.journals[0].set_default();
+        }
+
+        Ok(journal)
+    }
+
+    pub fn set_default_journal(&mut self, name: &str) -> Result<(), String> {
+        let chosen = self.position(name)?;
+        for i in 0..self.journals.len() {
+            let default = i == chosen;
+            if self.journals[i].is_default() != default {
+                self.rebuild(i, |d| { d.insert("default".to_string(), Value::Bool(default)); })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Only the config changes, moving the file is up to the caller
+    pub fn set_journal_path(&mut self, name: &str, path: &str) -> Result<(), String> {
+        let i = self.position(name)?;
+        if self.journal_path(name)?.is_none() {
+            return Err(format!("The journal {} isn't kept in a file", name));
+        }
+
+        self.rebuild(i, |d| { d.insert("path".to_string(), Value::String(path.to_string())); })
+    }
+
+    // Exactly one journal has to be the default once there are journals
+    pub fn validate(&self) -> Result<(), String> {
+        let defaults: Vec<&String> = self.journals.iter()
+            .filter(|j| j.is_default())
+            .map(|j| j.name())
+            .collect();
+
+        match defaults.len() {
+            1 => Ok(()),
+            0 if self.journals.is_empty() => Ok(()),
+            0 => Err("No journal is the default one, pick it with `rjrn journal set-default`".to_string()),
+            _ => Err(format!("Journals {} are all marked as default, pick one with \
+                              `rjrn journal set-default`",
+                             defaults.iter().map(|n| n.as_str()).collect::<Vec<&str>>().join(", ")))
+        }
+    }
+
+    fn position(&self, name: &str) -> Result<usize, String> {
+        self.journals.iter().position(|j| j.name() == name)
+            .ok_or(format!("There's no journal named {}", name))
+    }
+
+    // Journals are changed through their JSON, so it works for every backend
+    fn rebuild<F: FnOnce(&mut Map<String, Value>)>(&mut self, i: usize, change: F) -> Result<(), String> {
+        let mut json = self.journals[i].to_json();
+        match settings(&mut json) {
+            Some(d) => change(d),
+            None => return Err(format!("Couldn't change the journal {}", self.journals[i].name()))
+        }
+
+        self.journals[i] = self.registry.from_json(&json)?;
+        Ok(())
+    }
+
+    pub fn journal_with_name_or_default(&self, name: &str) ->
+        Option<&Box<dyn Journal>> {
+            self.journals
                 .iter()
                 .fold(None, |result, journal| {
                     if journal.name() == name {
@@ -152,7 +576,7 @@ impl Config {
 
                     match result {
                         None => {
-                            if journal.is_default() && name.len() == 0 { Some(journal) }
+                            if journal.is_default() && name.is_empty() { Some(journal) }
                             else { None }
                         },
                         Some(j) => Some(j)
@@ -161,15 +585,311 @@ impl Config {
     }
 }
 
+impl FromStr for Config {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Config, String> {
+        Config::from_str_with_registry(s, JournalRegistry::with_builtins())
+    }
+}
+
+// Lowercase letters, digits, `-` and `_` are kept, every other byte is
+// written as `%XX`, so different names never share a file, not even on
+// file systems ignoring case.
+fn file_name(journal: &str) -> String {
+    journal.bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' => (b as char).to_string(),
+            _ => format!("%{:02X}", b)
+        })
+        .collect()
+}
+
+fn legacy_file_name(journal: &str) -> String {
+    journal.chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+// Object with the name, path and default flag of a journal. Decorators like
+// the git journal keep the journal they wrap in `journal`.
+fn settings(json: &mut Value) -> Option<&mut Map<String, Value>> {
+    match *json {
+        Value::Object(ref mut d) => {
+            if d.get("journal").map(|j| j.is_object()).unwrap_or(false) {
+                settings(d.get_mut("journal").unwrap())
+            } else {
+                Some(d)
+            }
+        },
+        _ => None
+    }
+}
 
 impl PartialEq for Config {
      fn eq(&self, other: &Config) -> bool {
-         for (a, b) in self.file_journals.iter().zip(other.file_journals.iter()) {
-             if a != b {
+         for (a, b) in self.journals.iter().zip(other.journals.iter()) {
+             if a.to_json() != b.to_json() {
                  return false;
              }
          }
 
-         return true;
+         true
      }
  }
+
+#[cfg(test)]
+mod tests {
+    use journal::{Journal, JournalOptions};
+    use memory_journal::MemoryJournal;
+    use operation_log::Change;
+    use std::fs;
+    use test_support::{entry, memory_config, registry, TempDir};
+    use trash::Trash;
+    use super::{file_name, Config};
+
+    // Config saved in `dir`, with memory journals named `names`
+    fn file_config(dir: &TempDir, names: &[&str]) -> Config {
+        fs::create_dir_all(dir.join("")).unwrap();
+        let mut config = Config::load_from(&dir.join(".rjrn.config"), registry()).unwrap();
+        for name in names.iter() {
+            config.journals.push(Box::new(MemoryJournal::new(name)));
+        }
+        config
+    }
+
+    #[test]
+    fn add_rm_undo_redo_without_a_config_file() {
+        let config = memory_config(r#"{"journals": [{"type": "MemoryJournal", "name": "notes", "default": true}]}"#);
+        let journal = config.journal_with_name_or_default("").unwrap();
+        assert!(config.path().is_none());
+
+        let note = entry("Milk and eggs");
+        journal.upsert_entry(note.clone()).unwrap();
+        config.record(journal, "Add entry", vec![Change::new("notes", None, Some(note.clone()))]).unwrap();
+
+        config.trash_entries(journal, journal.entries().unwrap()).unwrap();
+        config.record(journal, "Remove 1 entries", vec![Change::new("notes", Some(note.clone()), None)]).unwrap();
+        assert!(journal.entries().unwrap().is_empty());
+        assert_eq!(config.trash(journal).entries().unwrap().len(), 1);
+        assert!(config.trash(journal).path().is_none());
+
+        assert_eq!(config.undo(journal).unwrap().unwrap().description(), "Remove 1 entries");
+        assert_eq!(journal.entries().unwrap()[0].id(), note.id());
+        assert!(config.trash(journal).entries().unwrap().is_empty());
+
+        assert_eq!(config.redo(journal).unwrap().unwrap().description(), "Remove 1 entries");
+        assert!(journal.entries().unwrap().is_empty());
+        assert_eq!(config.trash(journal).entries().unwrap()[0].entry().id(), note.id());
+        assert!(config.redo(journal).unwrap().is_none());
+
+        config.undo(journal).unwrap();
+        assert_eq!(config.undo(journal).unwrap().unwrap().description(), "Add entry");
+        assert!(journal.entries().unwrap().is_empty());
+        assert!(config.undo(journal).unwrap().is_none());
+    }
+
+    #[test]
+    fn route_finds_journals_by_name_or_alias() {
+        let config = memory_config(r#"{"journals": [{"type": "MemoryJournal", "name": "notes", "default": true},
+                                                    {"type": "MemoryJournal", "name": "work"}],
+                                      "aliases": {"work": ["w", "job"]}}"#);
+        assert_eq!(config.route("work").unwrap().name(), "work");
+        assert_eq!(config.route("job:").unwrap().name(), "work");
+        assert_eq!(config.route("notes").unwrap().name(), "notes");
+        assert!(config.route("fixed").is_none());
+        assert!(config.route(":").is_none());
+    }
+
+    #[test]
+    fn every_journal_has_its_own_memory_trash() {
+        let config = memory_config(r#"{"journals": [{"type": "MemoryJournal", "name": "notes", "default": true},
+                                                    {"type": "MemoryJournal", "name": "work"}]}"#);
+        let notes = config.journal_with_name_or_default("notes").unwrap();
+        let work = config.journal_with_name_or_default("work").unwrap();
+
+        notes.upsert_entry(entry("Removed")).unwrap();
+        config.trash_entries(notes, notes.entries().unwrap()).unwrap();
+
+        assert_eq!(config.trash(notes).entries().unwrap().len(), 1);
+        assert!(config.trash(work).entries().unwrap().is_empty());
+        assert!(memory_config("").trash(notes).entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn journal_files_are_next_to_the_config_file() {
+        let dir = TempDir::new("config");
+        let config = file_config(&dir, &["notes"]);
+        let journal = &config.journals[0];
+
+        assert_eq!(config.trash(journal).path(), Some(dir.join(".rjrn.trash/notes.json").as_path()));
+        assert_eq!(config.operation_log(journal).path(), Some(dir.join(".rjrn.undo/notes.json").as_path()));
+    }
+
+    #[test]
+    fn file_names_differ_for_every_journal_name() {
+        let names = ["my notes", "my_notes", "my/notes", "my%20notes", "My notes", "notes", "ünïcode", ""];
+        let mut files: Vec<String> = names.iter().map(|n| file_name(n).to_lowercase()).collect();
+        assert_eq!(file_name("my notes"), "my%20notes");
+        assert_eq!(file_name("notes"), "notes");
+        assert!(files.iter().all(|f| !f.contains('/')));
+
+        files.sort();
+        files.dedup();
+        assert_eq!(files.len(), names.len());
+    }
+
+    #[test]
+    fn colliding_names_keep_separate_trash() {
+        let dir = TempDir::new("config");
+        let config = file_config(&dir, &["my notes", "my_notes", "my/notes"]);
+
+        for journal in config.journals.iter() {
+            journal.upsert_entry(entry(&format!("Removed from {}", journal.name()))).unwrap();
+            config.trash_entries(journal, journal.entries().unwrap()).unwrap();
+        }
+
+        for journal in config.journals.iter() {
+            let trashed = config.trash(journal).entries().unwrap();
+            assert_eq!(trashed.len(), 1);
+            assert_eq!(trashed[0].journal(), journal.name().as_str());
+            assert!(config.trash(journal).path().unwrap().starts_with(dir.join(".rjrn.trash")));
+        }
+    }
+
+    // Trash as the old file name had it
+    fn legacy_trash(dir: &TempDir, journal: &Box<dyn Journal>) {
+        Trash::new(&dir.join(".rjrn.trash/my_notes.json"), 100, journal)
+            .put(journal.name(), vec![Box::new(entry("Old"))]).unwrap();
+    }
+
+    #[test]
+    fn trash_saved_under_the_old_file_name_is_taken_over() {
+        let dir = TempDir::new("config");
+        let config = file_config(&dir, &["my notes"]);
+        let journal = &config.journals[0];
+        legacy_trash(&dir, journal);
+
+        assert_eq!(config.trash(journal).entries().unwrap().len(), 1);
+        assert!(!dir.join(".rjrn.trash/my_notes.json").exists());
+    }
+
+    #[test]
+    fn old_trash_two_journals_could_own_is_left_alone() {
+        let dir = TempDir::new("config");
+        let config = file_config(&dir, &["my notes", "My notes"]);
+        legacy_trash(&dir, &config.journals[0]);
+
+        assert!(config.trash(&config.journals[0]).entries().unwrap().is_empty());
+        assert!(config.trash(&config.journals[1]).entries().unwrap().is_empty());
+        assert!(dir.join(".rjrn.trash/my_notes.json").exists());
+    }
+
+    fn trashed(config: &Config, name: &str) -> usize {
+        let journal = config.journal_with_name_or_default(name).unwrap();
+        config.trash(journal).entries().unwrap().len()
+    }
+
+    #[test]
+    fn renamed_journals_keep_their_trash() {
+        let dir = TempDir::new("config");
+        let mut config = file_config(&dir, &["notes"]);
+        config.set_aliases("notes", vec!["n".to_string()]);
+        config.trash_entries(&config.journals[0], vec![Box::new(entry("Milk"))]).unwrap();
+
+        config.rename_journal("notes", "diary").unwrap();
+        assert_eq!(trashed(&config, "diary"), 1);
+        assert_eq!(config.aliases("diary"), ["n"]);
+        assert!(!dir.join(".rjrn.trash/notes.json").exists());
+        assert_eq!(Config::load_from(&dir.join(".rjrn.config"), registry()).unwrap().journals[0].name(), "diary");
+    }
+
+    #[test]
+    fn failed_rename_keeps_the_old_name_and_trash() {
+        let dir = TempDir::new("config");
+        let mut config = file_config(&dir, &["notes"]);
+        config.trash_entries(&config.journals[0], vec![Box::new(entry("Milk"))]).unwrap();
+        // The config can't be replaced by a file any more
+        let _ = fs::remove_file(dir.join(".rjrn.config"));
+        fs::create_dir(dir.join(".rjrn.config")).unwrap();
+
+        assert!(config.rename_journal("notes", "diary").is_err());
+        assert_eq!(config.journals[0].name(), "notes");
+        assert_eq!(trashed(&config, "notes"), 1);
+        assert!(!dir.join(".rjrn.trash/diary.json").exists());
+    }
+
+    #[test]
+    fn removed_journals_leave_no_trash_behind() {
+        let mut config = memory_config(r#"{"journals": [{"type": "MemoryJournal", "name": "notes", "default": true},
+                                                        {"type": "MemoryJournal", "name": "work"}]}"#);
+        config.trash_entries(&config.journals[0], vec![Box::new(entry("Milk"))]).unwrap();
+
+        config.remove_journal("notes").unwrap();
+        config.journals.push(Box::new(MemoryJournal::new("notes")));
+        assert_eq!(trashed(&config, "notes"), 0);
+
+        let dir = TempDir::new("config");
+        let mut config = file_config(&dir, &["notes"]);
+        config.trash_entries(&config.journals[0], vec![Box::new(entry("Milk"))]).unwrap();
+        config.trash_entries(&config.journals[0], vec![Box::new(entry("Eggs"))]).unwrap();
+        config.remove_journal("notes").unwrap();
+        assert!(!dir.join(".rjrn.trash/notes.json").exists());
+        assert!(!dir.join(".rjrn.trash/notes.json.bak").exists());
+    }
+
+    #[test]
+    fn removing_the_default_makes_the_next_journal_default() {
+        let mut config = memory_config(r#"{"journals": [{"type": "MemoryJournal", "name": "notes", "default": true},
+                                                        {"type": "MemoryJournal", "name": "work"}]}"#);
+        config.remove_journal("notes").unwrap();
+        assert!(config.journals[0].is_default());
+        assert!(config.validate().is_ok());
+
+        config.remove_journal("work").unwrap();
+        assert!(config.validate().is_ok());
+        assert!(config.remove_journal("work").is_err());
+    }
+
+    #[test]
+    fn set_default_leaves_one_default() {
+        let mut config = memory_config(r#"{"journals": [{"type": "MemoryJournal", "name": "notes", "default": true},
+                                                        {"type": "MemoryJournal", "name": "work"}]}"#);
+        config.set_default_journal("work").unwrap();
+        assert_eq!(config.journal_with_name_or_default("").unwrap().name(), "work");
+        assert!(!config.journals[0].is_default());
+        assert!(config.set_default_journal("diary").is_err());
+    }
+
+    #[test]
+    fn validate_needs_exactly_one_default() {
+        let none = memory_config(r#"{"journals": [{"type": "MemoryJournal", "name": "notes"}]}"#);
+        assert!(none.validate().is_err());
+
+        let both = memory_config(r#"{"journals": [{"type": "MemoryJournal", "name": "notes", "default": true},
+                                                  {"type": "MemoryJournal", "name": "work", "default": true}]}"#);
+        assert!(both.validate().unwrap_err().contains("notes, work"));
+    }
+
+    #[test]
+    fn add_journal_with_options_makes_the_first_one_default() {
+        let mut config = memory_config(r#"{"journals": []}"#);
+        config.add_journal_with_options("memory", &JournalOptions::new("notes"), false).unwrap();
+        config.add_journal_with_options("MemoryJournal", &JournalOptions::new("work"), false).unwrap();
+        assert_eq!(config.journal_with_name_or_default("").unwrap().name(), "notes");
+
+        config.add_journal_with_options("memory", &JournalOptions::new("diary"), true).unwrap();
+        assert_eq!(config.journal_with_name_or_default("").unwrap().name(), "diary");
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn add_journal_with_options_checks_the_options() {
+        let mut config = memory_config(r#"{"journals": [{"type": "MemoryJournal", "name": "notes", "default": true}]}"#);
+        assert!(config.add_journal_with_options("memory", &JournalOptions::new("notes"), false).is_err());
+        assert!(config.add_journal_with_options("memory", &JournalOptions::new(" "), false).is_err());
+        assert!(config.add_journal_with_options("paper", &JournalOptions::new("work"), false).unwrap_err().contains("memory"));
+        assert!(config.add_journal_with_options("file", &JournalOptions::new("work"), false).unwrap_err().contains("--path"));
+        assert_eq!(config.journals.len(), 1);
+    }
+}
@@ -0,0 +1,109 @@
+use std::env;
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use uuid::Uuid;
+
+const DEFAULT_EDITOR: &str = "vi";
+
+// `$VISUAL`, then `$EDITOR`, they can have arguments like `code --wait`
+fn editor_command() -> String {
+    for var in ["VISUAL", "EDITOR"].iter() {
+        match env::var(var) {
+            Ok(ref editor) if !editor.trim().is_empty() => return editor.clone(),
+            _ => ()
+        }
+    }
+
+    DEFAULT_EDITOR.to_string()
+}
+
+// Directory only the user can read, removed with everything in it when dropped
+struct PrivateDir {
+    path: PathBuf,
+}
+
+impl PrivateDir {
+    fn create() -> io::Result<PrivateDir> {
+        let path = env::temp_dir().join(format!("rjrn-{}", Uuid::new_v4().simple()));
+        private_dir_builder().create(&path)?;
+        Ok(PrivateDir { path })
+    }
+}
+
+impl Drop for PrivateDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(unix)]
+fn private_dir_builder() -> DirBuilder {
+    use std::os::unix::fs::DirBuilderExt;
+    let mut builder = DirBuilder::new();
+    builder.mode(0o700);
+    builder
+}
+
+#[cfg(not(unix))]
+fn private_dir_builder() -> DirBuilder {
+    DirBuilder::new()
+}
+
+// Fails when the file already exists, so nobody can put it there first
+fn create_private(path: &Path) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    options.open(path)
+}
+
+// Opens `text` in the editor and returns the saved text. The file name ends
+// with `file_name` so editors can pick the syntax from its extension.
+pub fn edit(text: &str, file_name: &str) -> Result<String, String> {
+    let dir = PrivateDir::create()
+              .map_err(|why| format!("Couldn't create a temporary directory because: {}",
+                                     why))?;
+    let path = dir.path.join(file_name);
+
+    create_private(&path)
+    .and_then(|mut file| file.write_all(text.as_bytes()))
+    .map_err(|why| format!("Couldn't create {} because: {}",
+                           path.display(), why))?;
+
+    let result = run_editor(path.to_str().unwrap()).and_then(|_| {
+        let mut edited = String::new();
+        File::open(&path)
+            .and_then(|mut file| file.read_to_string(&mut edited))
+            .map(|_| edited)
+            .map_err(|why| format!("Couldn't read {} because: {}",
+                                   path.display(), why))
+    });
+
+    drop(dir);
+    result
+}
+
+fn run_editor(path: &str) -> Result<(), String> {
+    let editor = editor_command();
+    debug!("Opening {} in {}", path, editor);
+
+    let status = Command::new("sh").arg("-c").arg(format!("{} \"$1\"", editor))
+                 .arg("sh").arg(path)
+                 .status()
+                 .map_err(|why| format!("Couldn't start {} because: {}",
+                                        editor, why))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} exited with {}, nothing was saved", editor, status))
+    }
+}
@@ -0,0 +1,515 @@
+//! Encrypted File Journal keeps the same list of entries as `FileJournal`,
+//! encrypted with ChaCha20-Poly1305 under a key derived from a passphrase
+//! with Argon2id. The file is JSON with the KDF parameters, salt, nonce and
+//! the ciphertext.
+//!
+//! The passphrase is read once per run from the `RJRN_PASSPHRASE` environment
+//! variable (the name can be changed in the config), the output of
+//! `passphrase_command` (e.g. `pass show rjrn`) or asked for on the terminal.
+//! Nothing is written when the existing file can't be decrypted.
+//! Changes are made under the same lock as `FileJournal`'s.
+//!
+//! Usage for bootstraping and saving:
+//!
+//!```
+//!    use rjrn::journal::Journal;
+//!    use rjrn::encrypted_file_journal::EncryptedFileJournal;
+//!    let journal = EncryptedFileJournal::new("name", "path.json.enc");
+//!    assert_eq!(journal, EncryptedFileJournal::from_json(&journal.to_json()).unwrap());
+//!```
+
+use argon2::{self, Argon2, Algorithm, Version, Params};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use getrandom;
+use rpassword;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde_json::{self, Map, Value};
+use std::cell::RefCell;
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+use uuid::Uuid;
+
+use journal::{config_string, file_with_siblings, Journal, JournalFactory, JournalOptions, JournalRegistry};
+use entry::Entry;
+use prompt;
+use atomic_write;
+use file_lock::{self, FileLock};
+
+const FORMAT: &str = "rjrn-encrypted";
+const VERSION: u64 = 1;
+const DEFAULT_PASSPHRASE_ENV: &str = "RJRN_PASSPHRASE";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+// What's needed from the header of an encrypted file to derive its key
+#[derive(Clone, PartialEq)]
+struct KdfParams {
+    salt: Vec<u8>,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+}
+
+pub struct EncryptedFileJournal {
+    name: String,
+    path: String,
+    default: bool,
+    passphrase_env: String,
+    passphrase_command: Option<String>,
+    // How long to wait for other rjrn processes writing to the same file
+    lock_timeout_ms: u64,
+    // Asked for once per run, the key is kept for the salt it was derived with
+    passphrase: RefCell<Option<String>>,
+    key: RefCell<Option<(KdfParams, Vec<u8>)>>,
+}
+
+impl Journal for EncryptedFileJournal {
+    fn to_json(&self) -> Value {
+        let mut d = Map::new();
+        d.insert("name".to_string(), Value::String(self.name.clone()));
+        d.insert("path".to_string(), Value::String(self.path.clone()));
+        d.insert("type".to_string(), Value::String("EncryptedFileJournal".to_string()));
+        d.insert("default".to_string(), Value::Bool(self.default));
+        d.insert("passphrase_env".to_string(), Value::String(self.passphrase_env.clone()));
+        if let Some(ref command) = self.passphrase_command {
+            d.insert("passphrase_command".to_string(), Value::String(command.clone()));
+        }
+        d.insert("lock_timeout_ms".to_string(), Value::from(self.lock_timeout_ms));
+        Value::Object(d)
+    }
+
+    fn set_default(&mut self) {
+        self.default = true
+    }
+
+    fn is_default(&self) -> bool {
+        self.default
+    }
+
+    fn name(&self) -> &String {
+        &self.name
+    }
+
+    fn entries(&self) -> Result<Vec<Box<Entry>>, String> {
+        let mut s = String::new();
+        match File::open(&self.path) {
+            Ok(mut file) => file.read_to_string(&mut s)
+                            .map_err(|why| format!("Couldn't read entries {}",
+                                                   why))?,
+            Err(_) => return Ok(vec![])
+        };
+
+        if s.trim().is_empty() {
+            return Ok(vec![]);
+        }
+
+        let plaintext = self.decrypt(&s)?;
+        serde_json::from_str(&plaintext)
+            .map_err(|why| format!("Couldn't read decrypted entries: {}", why))
+    }
+
+    fn upsert_entry(&self, entry: Entry) -> Result<Uuid, String> {
+        debug!("Add entry with title:: {:?} to - {:?}", entry.title(), self.path);
+        let id = *entry.id();
+        let _lock = self.lock()?;
+        let mut entries = self.entries()?;
+
+        let mut entry = entry;
+        match entries.iter().position(|e| e.id() == entry.id()) {
+            Some(i) => {
+                entry.keep_history_of(&entries[i]);
+                *entries[i] = entry;
+            },
+            None => entries.push(Box::new(entry))
+        }
+
+        self.save_entries(&entries)?;
+        Ok(id)
+    }
+
+    fn remove_entries(&self, ids: &Vec<Uuid>) -> Result<(), String> {
+        let _lock = self.lock()?;
+        self.save_entries(
+            &self.entries()?
+                  .into_iter()
+                  .filter(|e| !ids.contains(e.id()))
+                  .collect())
+    }
+
+    fn files(&self) -> Vec<PathBuf> {
+        file_with_siblings(&self.path, &[])
+    }
+
+    // Encrypted with the key of the journal
+    fn seal(&self, text: &str) -> Result<String, String> {
+        let params = self.params()?;
+        self.encrypt(text, &params)
+    }
+
+    fn unseal(&self, text: &str) -> Result<String, String> {
+        self.decrypt(text)
+    }
+}
+
+impl EncryptedFileJournal {
+    pub fn new(name: &str, path: &str) -> EncryptedFileJournal {
+        EncryptedFileJournal {
+            name: name.to_string(),
+            path: path.to_string(),
+            default: false,
+            passphrase_env: DEFAULT_PASSPHRASE_ENV.to_string(),
+            passphrase_command: None,
+            lock_timeout_ms: file_lock::DEFAULT_TIMEOUT_MS,
+            passphrase: RefCell::new(None),
+            key: RefCell::new(None),
+        }
+    }
+
+    pub fn from_json(j: &Value) -> Result<EncryptedFileJournal, String> {
+        let mut journal = EncryptedFileJournal::new(&config_string(j, "name")?,
+                                                    &config_string(j, "path")?);
+        journal.default = j.get("default").and_then(|d| d.as_bool()).unwrap_or(false);
+
+        if let Some(env) = j.get("passphrase_env").and_then(|e| e.as_str()) {
+            journal.passphrase_env = env.to_string();
+        }
+        journal.passphrase_command = j.get("passphrase_command")
+            .and_then(|c| c.as_str())
+            .map(|c| c.to_string());
+        if let Some(timeout) = j.get("lock_timeout_ms").and_then(|t| t.as_u64()) {
+            journal.lock_timeout_ms = timeout;
+        }
+
+        Ok(journal)
+    }
+
+    // Used by the CLI when the user want to create a new journal of that type
+    pub fn bootstrap_cli() -> Result<EncryptedFileJournal, String> {
+        let name = prompt::journal_name();
+        let path = prompt::journal_path(&format!("rjrn-{}.json.enc", name))?;
+        let command = prompt::read_line(
+            &format!("Command printing the passphrase, e.g. `pass show rjrn` \
+                      (empty to use ${} or ask every time):", DEFAULT_PASSPHRASE_ENV));
+
+        let mut journal = EncryptedFileJournal::new(&name, &path);
+        if !command.is_empty() {
+            journal.passphrase_command = Some(command);
+        }
+        Ok(journal)
+    }
+
+    // Held while entries are read, changed and saved, so no other process saves in between
+    fn lock(&self) -> Result<FileLock, String> {
+        FileLock::acquire(Path::new(&self.path), Duration::from_millis(self.lock_timeout_ms))
+    }
+
+    fn save_entries(&self, entries: &Vec<Box<Entry>>) -> Result<(), String> {
+        let params = self.params()?;
+        let text = self.encrypt(&serde_json::to_string(&entries).unwrap(), &params)?;
+        atomic_write::write(Path::new(&self.path), text.as_bytes())
+            .map_err(|why| format!("Couldn't save the journal file because: {}", why))
+    }
+
+    // Those of the journal's key, or a new salt for a new journal. Refuses to
+    // write with a key that can't decrypt the journal, the key is cached so it's cheap.
+    fn params(&self) -> Result<KdfParams, String> {
+        self.entries()?;
+
+        match *self.key.borrow() {
+            Some((ref params, _)) => Ok(params.clone()),
+            None => Ok(KdfParams {
+                salt: random_bytes(SALT_LEN)?,
+                m_cost: Params::DEFAULT_M_COST,
+                t_cost: Params::DEFAULT_T_COST,
+                p_cost: Params::DEFAULT_P_COST,
+            })
+        }
+    }
+
+    fn encrypt(&self, plaintext: &str, params: &KdfParams) -> Result<String, String> {
+        let key = self.key_for(params, true)?;
+        let nonce = random_bytes(NONCE_LEN)?;
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+        let ciphertext = cipher.encrypt(Nonce::from_slice(&nonce), plaintext.as_bytes())
+                         .map_err(|_| "Couldn't encrypt the journal".to_string())?;
+
+        let mut d = Map::new();
+        d.insert("format".to_string(), Value::String(FORMAT.to_string()));
+        d.insert("version".to_string(), Value::from(VERSION));
+        d.insert("kdf".to_string(), Value::String("argon2id".to_string()));
+        d.insert("m_cost".to_string(), Value::from(params.m_cost as u64));
+        d.insert("t_cost".to_string(), Value::from(params.t_cost as u64));
+        d.insert("p_cost".to_string(), Value::from(params.p_cost as u64));
+        d.insert("salt".to_string(), Value::String(STANDARD.encode(&params.salt)));
+        d.insert("cipher".to_string(), Value::String("chacha20poly1305".to_string()));
+        d.insert("nonce".to_string(), Value::String(STANDARD.encode(&nonce)));
+        d.insert("ciphertext".to_string(), Value::String(STANDARD.encode(&ciphertext)));
+        Ok(serde_json::to_string(&Value::Object(d)).unwrap())
+    }
+
+    fn decrypt(&self, text: &str) -> Result<String, String> {
+        let envelope = serde_json::from_str::<Value>(text)
+                       .map_err(|_| format!("{} isn't an encrypted journal", self.path))?;
+
+        if envelope.get("format").and_then(|f| f.as_str()) != Some(FORMAT) {
+            return Err(format!("{} isn't an encrypted journal", self.path));
+        }
+        if envelope.get("version").and_then(|v| v.as_u64()) != Some(VERSION) {
+            return Err(format!("Unsupported version of encrypted journal {}", self.path));
+        }
+
+        let params = KdfParams {
+            salt: base64_field(&envelope, "salt")?,
+            m_cost: u32_field(&envelope, "m_cost")?,
+            t_cost: u32_field(&envelope, "t_cost")?,
+            p_cost: u32_field(&envelope, "p_cost")?,
+        };
+        let nonce = base64_field(&envelope, "nonce")?;
+        let ciphertext = base64_field(&envelope, "ciphertext")?;
+        if nonce.len() != NONCE_LEN {
+            return Err(format!("Invalid nonce in encrypted journal {}", self.path));
+        }
+
+        let key = self.key_for(&params, false)?;
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+
+        match cipher.decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref()) {
+            Ok(plaintext) => {
+                *self.key.borrow_mut() = Some((params, key));
+                String::from_utf8(plaintext)
+                    .map_err(|_| format!("Decrypted journal {} isn't valid UTF-8", self.path))
+            },
+            Err(_) => {
+                // Don't keep a wrong passphrase for the rest of the run
+                *self.passphrase.borrow_mut() = None;
+                *self.key.borrow_mut() = None;
+                Err(format!("Couldn't decrypt {}: wrong passphrase or the file is damaged",
+                            self.path))
+            }
+        }
+    }
+
+    fn key_for(&self, params: &KdfParams, new_file: bool) -> Result<Vec<u8>, String> {
+        if let Some((ref cached, ref key)) = *self.key.borrow() {
+            if cached == params {
+                return Ok(key.clone());
+            }
+        }
+
+        let passphrase = self.passphrase(new_file)?;
+        let argon2_params = Params::new(params.m_cost, params.t_cost, params.p_cost,
+                                        Some(KEY_LEN))
+                            .map_err(kdf_error)?;
+        let mut key = vec![0u8; KEY_LEN];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, argon2_params)
+        .hash_password_into(passphrase.as_bytes(), &params.salt, &mut key)
+        .map_err(kdf_error)?;
+
+        *self.key.borrow_mut() = Some((params.clone(), key.clone()));
+        Ok(key)
+    }
+
+    // New journals ask twice, a typo would lock the journal for good
+    fn passphrase(&self, confirm: bool) -> Result<String, String> {
+        if let Some(ref passphrase) = *self.passphrase.borrow() {
+            return Ok(passphrase.clone());
+        }
+
+        let passphrase = match env::var(&self.passphrase_env) {
+            Ok(passphrase) => passphrase,
+            Err(_) => match self.passphrase_command {
+                Some(ref command) => run_passphrase_command(command)?,
+                None => {
+                    let question = format!("Passphrase for journal {}: ", self.name);
+                    let passphrase = rpassword::prompt_password(question)
+                                     .map_err(|why| format!("Couldn't read the passphrase: {}", why))?;
+                    if confirm {
+                        let again = rpassword::prompt_password("Repeat the passphrase: ")
+                                    .map_err(|why| format!("Couldn't read the passphrase: {}", why))?;
+                        if again != passphrase {
+                            return Err("Passphrases don't match".to_string());
+                        }
+                    }
+                    passphrase
+                }
+            }
+        };
+
+        if passphrase.is_empty() {
+            return Err("Passphrase can't be empty".to_string());
+        }
+
+        *self.passphrase.borrow_mut() = Some(passphrase.clone());
+        Ok(passphrase)
+    }
+}
+
+fn run_passphrase_command(command: &str) -> Result<String, String> {
+    let output = Command::new("sh").arg("-c").arg(command).output()
+                 .map_err(|why| format!("Couldn't run passphrase command: {}",
+                                        why))?;
+
+    if !output.status.success() {
+        return Err(format!("Passphrase command failed: {}", output.status));
+    }
+
+    String::from_utf8(output.stdout)
+        .map(|s| s.trim_end_matches(['\n', '\r']).to_string())
+        .map_err(|_| "Passphrase command printed invalid UTF-8".to_string())
+}
+
+fn random_bytes(len: usize) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![0u8; len];
+    getrandom::getrandom(&mut bytes)
+    .map_err(|why| format!("Couldn't get random bytes: {}", why))?;
+    Ok(bytes)
+}
+
+fn kdf_error(why: argon2::Error) -> String {
+    format!("Couldn't derive the key: {}", why)
+}
+
+fn base64_field(json: &Value, key: &str) -> Result<Vec<u8>, String> {
+    json.get(key)
+        .and_then(|v| v.as_str())
+        .and_then(|v| STANDARD.decode(v).ok())
+        .ok_or(format!("Encrypted journal has invalid `{}`", key))
+}
+
+fn u32_field(json: &Value, key: &str) -> Result<u32, String> {
+    json.get(key)
+        .and_then(|v| v.as_u64())
+        .map(|v| v as u32)
+        .ok_or(format!("Encrypted journal has invalid `{}`", key))
+}
+
+// Leaves the passphrase and the key out
+impl fmt::Debug for EncryptedFileJournal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "EncryptedFileJournal {{ name: {:?}, path: {:?}, default: {:?} }}",
+               self.name, self.path, self.default)
+    }
+}
+
+pub struct EncryptedFileJournalFactory;
+
+impl JournalFactory for EncryptedFileJournalFactory {
+    fn kind(&self) -> &'static str {
+        "EncryptedFileJournal"
+    }
+
+    fn description(&self) -> &'static str {
+        "Encrypted File Journal"
+    }
+
+    fn bootstrap_cli(&self, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        Ok(Box::new(EncryptedFileJournal::bootstrap_cli()?))
+    }
+
+    fn from_json(&self, journal: &Value, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        Ok(Box::new(EncryptedFileJournal::from_json(journal)?))
+    }
+
+    // `passphrase_command` and `passphrase_env` are optional, like in the config
+    fn from_options(&self, options: &JournalOptions, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        let mut journal = EncryptedFileJournal::new(&options.name, options.path()?);
+        journal.passphrase_command = options.get("passphrase_command").cloned();
+        if let Some(env) = options.get("passphrase_env") {
+            journal.passphrase_env = env.clone();
+        }
+        Ok(Box::new(journal))
+    }
+}
+
+impl PartialEq for EncryptedFileJournal {
+     fn eq(&self, other: &EncryptedFileJournal) -> bool {
+         self.path == other.path
+     }
+ }
+
+#[cfg(test)]
+mod tests {
+    use file_lock::FileLock;
+    use journal::Journal;
+    use serde_json;
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use std::time::Duration;
+    use test_support::{entry, TempDir};
+    use super::EncryptedFileJournal;
+
+    fn journal(dir: &TempDir) -> EncryptedFileJournal {
+        fs::create_dir_all(dir.join("")).unwrap();
+        let mut journal = EncryptedFileJournal::new("notes", &dir.join("journal.json.enc").to_string_lossy());
+        journal.lock_timeout_ms = 100;
+        *journal.passphrase.borrow_mut() = Some("correct horse".to_string());
+        journal
+    }
+
+    #[test]
+    fn changes_wait_for_the_lock() {
+        let dir = TempDir::new("encrypted");
+        let journal = journal(&dir);
+        let note = entry("Milk and eggs");
+
+        let lock = FileLock::acquire(Path::new(&journal.path), Duration::from_millis(100)).unwrap();
+        assert!(journal.upsert_entry(note.clone()).unwrap_err().contains("Timed out"));
+        assert!(journal.remove_entries(&vec![*note.id()]).is_err());
+
+        drop(lock);
+        journal.upsert_entry(note.clone()).unwrap();
+        journal.upsert_entry(entry("Bread")).unwrap();
+        assert_eq!(journal.entries().unwrap().len(), 2);
+        assert!(dir.join("journal.json.enc.bak").exists());
+    }
+
+    #[test]
+    fn passphrase_comes_from_the_configured_variable() {
+        let dir = TempDir::new("encrypted");
+        fs::create_dir_all(dir.join("")).unwrap();
+        env::set_var("RJRN_TEST_PASSPHRASE", "correct horse");
+        env::set_var("RJRN_TEST_WRONG_PASSPHRASE", "battery staple");
+        let config = |env: &str| serde_json::json!({
+            "name": "notes", "path": dir.join("journal.json.enc"), "passphrase_env": env
+        });
+
+        let journal = EncryptedFileJournal::from_json(&config("RJRN_TEST_PASSPHRASE")).unwrap();
+        journal.upsert_entry(entry("Milk and eggs")).unwrap();
+        let saved = fs::read_to_string(dir.join("journal.json.enc")).unwrap();
+        assert!(!saved.contains("Milk"));
+
+        let again = EncryptedFileJournal::from_json(&config("RJRN_TEST_PASSPHRASE")).unwrap();
+        assert_eq!(again.entries().unwrap()[0].content(), "Milk and eggs");
+
+        let wrong = EncryptedFileJournal::from_json(&config("RJRN_TEST_WRONG_PASSPHRASE")).unwrap();
+        assert!(wrong.entries().unwrap_err().contains("wrong passphrase"));
+        assert!(wrong.upsert_entry(entry("Bread")).is_err());
+        assert_eq!(fs::read_to_string(dir.join("journal.json.enc")).unwrap(), saved);
+    }
+
+    #[test]
+    fn passphrase_command_is_used_without_the_variable() {
+        let dir = TempDir::new("encrypted");
+        fs::create_dir_all(dir.join("")).unwrap();
+        let mut piped = EncryptedFileJournal::new("notes", &dir.join("journal.json.enc").to_string_lossy());
+        piped.passphrase_env = "RJRN_TEST_UNSET_PASSPHRASE".to_string();
+        piped.passphrase_command = Some("printf 'correct horse\\n'".to_string());
+        piped.upsert_entry(entry("Milk and eggs")).unwrap();
+
+        assert_eq!(journal(&dir).entries().unwrap()[0].content(), "Milk and eggs");
+
+        piped.passphrase_command = Some("exit 1".to_string());
+        *piped.passphrase.borrow_mut() = None;
+        *piped.key.borrow_mut() = None;
+        assert!(piped.entries().unwrap_err().contains("Passphrase command failed"));
+    }
+}
@@ -3,7 +3,7 @@ use std::fmt;
 use uuid::Uuid;
 use chrono::*;
 use std::str::FromStr;
-use rustc_serialize::{Encodable, Encoder, Decodable, Decoder};
+use serde::{de, Serialize, Serializer, Deserialize, Deserializer};
 
 
 #[derive(Debug)]
@@ -17,40 +17,27 @@ impl fmt::Display for EntryError {
     }
 }
 
-impl error::Error for EntryError {
-    fn description(&self) -> &str {
-        match *self {
-            EntryError::UnsuccessfulAdd => "Couldn't add entry - unsuccessful.",
-        }
-    }
-
-    fn cause(&self) -> Option<&error::Error> {
-        match *self {
-            EntryError::UnsuccessfulAdd => None
-        }
-    }
-}
+impl error::Error for EntryError {}
 
 #[derive(Debug, Clone, Copy)]
-struct DateTimeLocal(DateTime<UTC>);
+struct DateTimeLocal(DateTime<Utc>);
 
-impl Encodable for DateTimeLocal {
-    fn encode<S: Encoder>(&self, e: &mut S) -> Result<(), S::Error> {
-        e.emit_str(&self.0.to_rfc3339())
+impl Serialize for DateTimeLocal {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&self.0.to_rfc3339())
     }
 }
 
-impl Decodable for DateTimeLocal {
-    fn decode<D: Decoder>(d: &mut D) -> Result<DateTimeLocal, D::Error> {
-        Ok(DateTimeLocal(DateTime::<UTC>::from_str(&d.read_str()
-                                                      .ok()
-                                                      .expect("Couldn't parse DateTime from json"))
-                         .ok()
-                         .expect("Couldn't parse DateTime from str")))
+impl<'de> Deserialize<'de> for DateTimeLocal {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<DateTimeLocal, D::Error> {
+        let s = String::deserialize(d)?;
+        DateTime::<Utc>::from_str(&s)
+            .map(DateTimeLocal)
+            .map_err(|why| de::Error::custom(format!("Couldn't parse DateTime from {}: {}", s, why)))
     }
 }
 
-#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Deserialize, Serialize)]
 #[derive(Debug, Clone)]
 pub struct Entry {
     id: Uuid,
@@ -60,6 +47,43 @@ pub struct Entry {
     created_at: Box<DateTimeLocal>,
     starred: bool,
     tags: Vec<String>,
+    // Older versions of the entry, oldest first. Missing in entries saved before
+    // revisions were kept.
+    revisions: Option<Vec<Revision>>,
+}
+
+// What an entry looked like before it was updated
+#[derive(Deserialize, Serialize)]
+#[derive(Debug, Clone)]
+pub struct Revision {
+    title: Option<String>,
+    content: String,
+    starred: bool,
+    tags: Vec<String>,
+    updated_at: Box<DateTimeLocal>,
+}
+
+impl Revision {
+    pub fn title(&self) -> &Option<String> {
+        &self.title
+    }
+
+    pub fn content(&self) -> &String {
+        &self.content
+    }
+
+    pub fn starred(&self) -> bool {
+        self.starred
+    }
+
+    pub fn tags(&self) -> &Vec<String> {
+        &self.tags
+    }
+
+    // When this version was saved
+    pub fn updated_at(&self) -> DateTime<Utc> {
+        self.updated_at.0
+    }
 }
 
 impl Entry {
@@ -70,6 +94,62 @@ impl Entry {
     pub fn title(&self) -> &Option<String> {
         &self.title
     }
+
+    pub fn content(&self) -> &String {
+        &self.content
+    }
+
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.created_at.0
+    }
+
+    pub fn updated_at(&self) -> DateTime<Utc> {
+        self.updated_at.0
+    }
+
+    pub fn starred(&self) -> bool {
+        self.starred
+    }
+
+    pub fn tags(&self) -> &Vec<String> {
+        &self.tags
+    }
+
+    pub fn revisions(&self) -> &[Revision] {
+        match self.revisions {
+            Some(ref revisions) => revisions,
+            None => &[]
+        }
+    }
+
+    // Used by journals when `self` replaces `previous`: takes over its revisions
+    // and adds `previous` itself when the title, content, star or tags changed
+    pub fn keep_history_of(&mut self, previous: &Entry) {
+        let mut revisions = previous.revisions().to_vec();
+
+        let changed = self.title != previous.title || self.content != previous.content ||
+            self.starred != previous.starred || self.tags != previous.tags;
+        if changed {
+            revisions.push(Revision {
+                title: previous.title.clone(),
+                content: previous.content.clone(),
+                starred: previous.starred,
+                tags: previous.tags.clone(),
+                updated_at: previous.updated_at.clone(),
+            });
+        }
+
+        self.revisions = if revisions.is_empty() { None } else { Some(revisions) };
+    }
+
+    // For journals keeping revisions apart from the entries
+    pub fn set_revisions(&mut self, revisions: Vec<Revision>) {
+        self.revisions = if revisions.is_empty() { None } else { Some(revisions) };
+    }
+
+    pub fn forget_revisions(&mut self) {
+        self.revisions = None;
+    }
 }
 
 #[derive(Clone)]
@@ -83,9 +163,15 @@ pub struct EntryBuilder {
     tags: Vec<String>,
 }
 
+impl Default for EntryBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl EntryBuilder {
     pub fn new() -> EntryBuilder {
-        let created_at = DateTimeLocal(UTC::now());
+        let created_at = DateTimeLocal(Utc::now());
 
         EntryBuilder {
             id: Uuid::new_v4(),
@@ -98,6 +184,19 @@ impl EntryBuilder {
         }
     }
 
+    // Builder for a new version of an existing entry, without its revisions
+    pub fn from_entry(entry: &Entry) -> EntryBuilder {
+        EntryBuilder {
+            id: entry.id,
+            title: entry.title.clone(),
+            content: entry.content.clone(),
+            updated_at: entry.updated_at.clone(),
+            created_at: entry.created_at.clone(),
+            starred: entry.starred,
+            tags: entry.tags.clone(),
+        }
+    }
+
     pub fn id(&mut self, id: Uuid) -> &mut EntryBuilder {
         self.id = id;
         self
@@ -118,11 +217,28 @@ impl EntryBuilder {
             self.content = content;
         }
 
-        if self.title == Some("".to_string()) || self.title == None {
+        if self.title == Some("".to_string()) || self.title.is_none() {
             {
               self.set_title_from_content();
             }
         }
+
+        self.tags_from_text()
+    }
+
+    // Adds the `#tags` and `@mentions` of the title and the content
+    pub fn tags_from_text(&mut self) -> &mut EntryBuilder {
+        let text = format!("{} {}", self.title.clone().unwrap_or_default(), self.content);
+        for tag in extract_tags(&text).iter() {
+            self.tag(tag);
+        }
+        self
+    }
+
+    // Sets the content as it is, without looking for the star marker or the title.
+    // Used by journals reading back entries they have saved.
+    pub fn raw_content(&mut self, content: String) -> &mut EntryBuilder {
+        self.content = content;
         self
     }
 
@@ -131,9 +247,33 @@ impl EntryBuilder {
         self
     }
 
+    pub fn created_at(&mut self, created_at: DateTime<Utc>) -> &mut EntryBuilder {
+        *self.created_at = DateTimeLocal(created_at);
+        self
+    }
+
+    pub fn updated_at(&mut self, updated_at: DateTime<Utc>) -> &mut EntryBuilder {
+        *self.updated_at = DateTimeLocal(updated_at);
+        self
+    }
+
+    pub fn tags(&mut self, tags: Vec<String>) -> &mut EntryBuilder {
+        self.tags = tags;
+        self
+    }
+
+    // Adds the tag lowercased and without `#`/`@`, unless the entry already has it
+    pub fn tag(&mut self, tag: &str) -> &mut EntryBuilder {
+        let tag = normalize_tag(tag);
+        if !tag.is_empty() && !self.tags.contains(&tag) {
+            self.tags.push(tag);
+        }
+        self
+    }
+
     pub fn finalize(&self) -> Result<Entry, String> {
         // FIXME: do I really have to clone Strings here?
-        if self.content != "" {
+        if !self.content.is_empty() {
             Ok(Entry {
                 id: self.id,
                 title: self.title.clone(),
@@ -142,6 +282,7 @@ impl EntryBuilder {
                 created_at: self.created_at.clone(),
                 starred: self.starred,
                 tags: self.tags.clone(),
+                revisions: None,
             })
         } else {
             Err("Content is empty!".to_string())
@@ -149,8 +290,9 @@ impl EntryBuilder {
 
     }
 
-    fn set_title_from_content(&mut self) -> &mut EntryBuilder {
-        let dividers = vec!['\n', '?', '!', '.'];
+    // First sentence or line of the content
+    pub fn set_title_from_content(&mut self) -> &mut EntryBuilder {
+        let dividers = ['\n', '?', '!', '.'];
         self.title = Some(self.content
             .split(|c| dividers.contains(&c))
             .map(|c| c.to_string())
@@ -159,3 +301,69 @@ impl EntryBuilder {
         self
     }
 }
+
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim()
+        .trim_start_matches(['#', '@'])
+        .to_lowercase()
+}
+
+// `#tags` and `@mentions` starting a word. Trailing punctuation is dropped and
+// numbers like `#42` aren't tags.
+pub fn extract_tags(text: &str) -> Vec<String> {
+    let mut tags: Vec<String> = vec![];
+
+    for word in text.split_whitespace() {
+        if !word.starts_with('#') && !word.starts_with('@') {
+            continue;
+        }
+
+        let tag: String = word[1..].chars()
+            .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '-' || *c == '/')
+            .collect();
+        let tag = normalize_tag(tag.trim_end_matches(['-', '/']));
+
+        if !tag.is_empty() && !tag.chars().all(|c| c.is_numeric()) && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+
+    tags
+}
+
+// The entry as the journal saved it, some journals give entries their own ids
+pub fn saved_as(entry: Entry, id: Uuid) -> Entry {
+    if *entry.id() == id {
+        return entry;
+    }
+
+    EntryBuilder::from_entry(&entry).id(id).finalize().unwrap_or(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_tags, normalize_tag, EntryBuilder};
+
+    #[test]
+    fn tags_and_mentions_start_words() {
+        assert_eq!(extract_tags("Deploy #Work, with @Ana and #work again"), vec!["work", "ana"]);
+        assert_eq!(extract_tags("Issue #42 on email@example.com for C#"), Vec::<String>::new());
+        assert_eq!(extract_tags("#ci/cd-, #front-end. (#skipped)"), vec!["ci/cd", "front-end"]);
+        assert_eq!(extract_tags("Café #été"), vec!["été"]);
+        assert_eq!(normalize_tag(" @Ana "), "ana");
+    }
+
+    #[test]
+    fn builder_adds_the_tags_of_the_title_and_content() {
+        let mut builder = EntryBuilder::new();
+        builder.title("Release #v2".to_string())
+            .tag("#Work")
+            .content("Shipped to @ops. #work".to_string());
+        let entry = builder.finalize().unwrap();
+        assert_eq!(entry.tags(), &vec!["work", "v2", "ops"]);
+
+        let mut builder = EntryBuilder::new();
+        builder.tags(vec!["kept".to_string()]).raw_content("No #tags read".to_string());
+        assert_eq!(builder.finalize().unwrap().tags(), &vec!["kept"]);
+    }
+}
@@ -1,5 +1,5 @@
 //! File Journal which implements handlers for a journal that's on the file disk.
-//! It's the default and only handler right now.
+//! It's the default handler.
 //!
 //! Usage for bootstraping and saving:
 //!
@@ -7,78 +7,41 @@
 //!    use rjrn::journal::Journal;
 //!    use rjrn::file_journal::FileJournal;
 //!    let journal = FileJournal::new("name", "path");
-//!    assert_eq!(journal, FileJournal::from_json(&FileJournal::to_json(&journal)));
+//!    assert_eq!(journal, FileJournal::from_json(&FileJournal::to_json(&journal)).unwrap());
 //!```
 
-use rustc_serialize::json::{self, Json};
-use std::collections::BTreeMap;
-use std::io::{self, Write, Read};
-use std::error::Error;
+use serde_json::{self, Map, Value};
+use std::io::{self, Read};
 use std::fs::{self, OpenOptions, File};
-use std::env;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use uuid::Uuid;
 
-use journal::{Journal};
+use journal::{config_string, file_with_siblings, Journal, JournalFactory, JournalOptions, JournalRegistry};
 use entry::Entry;
+use encrypted_file_journal::EncryptedFileJournal;
+use prompt;
+use atomic_write;
+use file_lock::{self, FileLock};
 
 #[derive(Debug)]
 pub struct FileJournal {
     name: String,
     path: String,
     default: bool,
+    // How long to wait for other rjrn processes writing to the same file
+    lock_timeout_ms: u64,
 }
 
 impl Journal for FileJournal {
-    fn from_json(j: &Json) -> FileJournal {
-     FileJournal {
-         name: String::from(j.find("name").unwrap().as_string().unwrap()),
-         path: String::from(j.find("path").unwrap().as_string().unwrap()),
-         default: j.find("default").unwrap().as_boolean().unwrap(),
-      }
-    }
-
-    fn to_json(&self) -> Json {
-        let mut d: BTreeMap<String, Json> = BTreeMap::new();
-        d.insert("name".to_string(), Json::String(self.name.clone()));
-        d.insert("path".to_string(), Json::String(self.path.clone()));
-        d.insert("type".to_string(), Json::String("FileJournal".to_string()));
-        d.insert("default".to_string(), Json::Boolean(self.default));
-        Json::Object(d)
-    }
-
-    // Used by the CLI when the user want to create a new journal of that type
-    fn bootstrap_cli() -> Result<FileJournal, String> {
-        println!("Name of the journal (default):");
-
-        let mut name = String::new();
-        io::stdin().read_line(&mut name)
-            .ok()
-            .expect("Failed to parse name");
-
-        if name.trim().is_empty() {
-            name.push_str("default");
-        }
-
-        let default_path = format!("rjrn-{}.json", name.trim());
-        println!("Path of the journal (~/{}):", default_path);
-
-        let mut path = String::new();
-        io::stdin().read_line(&mut path)
-            .ok()
-            .expect("Failed to parse path");
-
-        if path.trim().is_empty() {
-            match env::home_dir() {
-                Some(ref p) => {
-                    Ok(FileJournal::new(name.trim(),
-                                     p.join(default_path)
-                                         .to_str().unwrap()))
-                },
-                None => Err("Impossible to get your home dir!".to_string())
-            }
-        } else {
-            Ok(FileJournal::new(name.trim(), path.trim()))
-        }
+    fn to_json(&self) -> Value {
+        let mut d = Map::new();
+        d.insert("name".to_string(), Value::String(self.name.clone()));
+        d.insert("path".to_string(), Value::String(self.path.clone()));
+        d.insert("type".to_string(), Value::String("FileJournal".to_string()));
+        d.insert("default".to_string(), Value::Bool(self.default));
+        d.insert("lock_timeout_ms".to_string(), Value::from(self.lock_timeout_ms));
+        Value::Object(d)
     }
 
     fn set_default(&mut self) {
@@ -95,54 +58,56 @@ impl Journal for FileJournal {
 
     fn entries(&self) -> Result<Vec<Box<Entry>>, String> {
         let mut s = String::new();
-        let mut file = self.content_file(false);
+        // Not created here, the first save creates it only readable by its owner
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(_) => return Ok(vec![])
+        };
 
         match file.read_to_string(&mut s) {
-            Err(why) => Err(format!("Couldn't read entries {}", Error::description(&why))),
+            Err(why) => Err(format!("Couldn't read entries {}", why)),
             Ok(_) => {
                 if s.is_empty() {
                     Ok(vec![])
                 } else {
-                    Ok(json::decode(&s).unwrap())
+                    Ok(serde_json::from_str(&s).unwrap())
                 }
             }
         }
     }
 
-    fn upsert_entry(&self, entry: Entry) -> Result<(), String> {
+    fn upsert_entry(&self, entry: Entry) -> Result<Uuid, String> {
         debug!("Add entry with title:: {:?} to - {:?}", entry.title(), self.path);
-        let mut entries = try!(self.entries());
+        let id = *entry.id();
+        let _lock = self.lock()?;
+        let mut entries = self.entries()?;
 
+        let mut entry = entry;
         match entries.iter().position(|e| e.id() == entry.id()) {
-            Some(i) => entries.insert(i, Box::new(entry)),
+            Some(i) => {
+                entry.keep_history_of(&entries[i]);
+                *entries[i] = entry;
+            },
             None => entries.push(Box::new(entry))
         }
 
-        self.save_entries(&entries)
+        self.save_entries(&entries)?;
+        Ok(id)
     }
 
     fn remove_entries(&self, ids: &Vec<Uuid>) -> Result<(), String> {
-        self.save_entries(
-            &try!(self.entries())
-                  .into_iter()
-                  .filter(|e| !ids.contains(e.id()))
-                  .collect())
+        let _lock = self.lock()?;
+        self.save_without(ids)
     }
 
-    fn undo_last_entry(&self) -> Result<(), String> {
-        match try!(self.entries()).iter().last() {
-            Some(e) => {
-                debug!("Removing from {}, entry with id: {}", self.path, e.id());
-                self.remove_entries(&vec![e.id().clone()])
-            },
-            None => { Ok(()) }
-        }
+    fn files(&self) -> Vec<PathBuf> {
+        file_with_siblings(&self.path, &[])
     }
 }
 
 
 // FIXME: inspect the error and print only relevant msg.
-const FILE_ERROR_MSG: &'static str =
+const FILE_ERROR_MSG: &str =
     "Couldn't open/create journal file because: \n
            - you don't have suffiecient permissions\n
            - there's already a file by that name in that location\n
@@ -150,39 +115,104 @@ const FILE_ERROR_MSG: &'static str =
 
 impl FileJournal {
     pub fn new(name: &str, path: &str) -> FileJournal {
-        FileJournal::validate_path(path);
+        FileJournal::check_path(path).expect(FILE_ERROR_MSG);
+        FileJournal::unchecked(name, path)
+    }
+
+    // Like `new`, with an error instead of a panic when the file can't be used
+    pub fn create(name: &str, path: &str) -> Result<FileJournal, String> {
+        FileJournal::check_path(path)?;
+        Ok(FileJournal::unchecked(name, path))
+    }
 
+    fn unchecked(name: &str, path: &str) -> FileJournal {
         FileJournal {
             name: name.to_string().clone(),
             path: path.to_string().clone(),
             default: false,
+            lock_timeout_ms: file_lock::DEFAULT_TIMEOUT_MS,
         }
     }
 
-    fn validate_path(path: &str) {
+    pub fn from_json(j: &Value) -> Result<FileJournal, String> {
+        Ok(FileJournal {
+            name: config_string(j, "name")?,
+            path: config_string(j, "path")?,
+            default: j.get("default").and_then(|d| d.as_bool()).unwrap_or(false),
+            lock_timeout_ms: j.get("lock_timeout_ms").and_then(|t| t.as_u64())
+                .unwrap_or(file_lock::DEFAULT_TIMEOUT_MS),
+        })
+    }
+
+    // Used by the CLI when the user want to create a new journal of that type
+    pub fn bootstrap_cli() -> Result<FileJournal, String> {
+        let name = prompt::journal_name();
+        let path = prompt::journal_path(&format!("rjrn-{}.json", name))?;
+
+        FileJournal::create(&name, &path)
+    }
+
+    // An existing journal is only opened, the test file is removed otherwise
+    fn check_path(path: &str) -> Result<(), String> {
         debug!("Validating path: {}", path);
-        OpenOptions::new().clone().create(true).open(path)
-            .ok()
-            .expect(FILE_ERROR_MSG);
-       fs::remove_file(path).ok().expect(FILE_ERROR_MSG);
+        let failed = |why: io::Error| format!("Couldn't open/create journal file {} because: {}",
+                                              path, why);
+        if Path::new(path).exists() {
+            return File::open(path).map(|_| ()).map_err(failed);
+        }
+
+        OpenOptions::new().write(true).create(true).truncate(false).open(path).map_err(&failed)?;
+        fs::remove_file(path).map_err(failed)
+    }
+
+    // Held while entries are read, changed and saved, so no other process saves in between
+    fn lock(&self) -> Result<FileLock, String> {
+        FileLock::acquire(Path::new(&self.path), Duration::from_millis(self.lock_timeout_ms))
     }
 
-    fn content_file(&self, truncate: bool) -> File {
-        OpenOptions::new().read(true).write(true).create(true).truncate(truncate).clone()
-            .open(&self.path)
-            .ok().
-            expect(FILE_ERROR_MSG)
+    fn save_without(&self, ids: &Vec<Uuid>) -> Result<(), String> {
+        self.save_entries(
+            &self.entries()?
+                  .into_iter()
+                  .filter(|e| !ids.contains(e.id()))
+                  .collect())
     }
 
+    // Written to a temporary file and renamed, the previous file is kept as `.bak`
     fn save_entries(&self, entries: &Vec<Box<Entry>>) -> Result<(), String> {
-        let mut file = self.content_file(true);
+        let content = format!("{}\n", serde_json::to_string(&entries).unwrap());
+
+        atomic_write::write(Path::new(&self.path), content.as_bytes())
+            .map_err(|why| format!("Couldn't save the journal file because: {}", why))
+    }
+}
+
+pub struct FileJournalFactory;
+
+impl JournalFactory for FileJournalFactory {
+    fn kind(&self) -> &'static str {
+        "FileJournal"
+    }
+
+    fn description(&self) -> &'static str {
+        "File Journal"
+    }
 
-        match writeln!(file, "{}", json::encode(&entries).unwrap()) {
-            Err(why) => Err(format!("Couldn't save the config file because: {}",
-                                    Error::description(&why))),
-            Ok(_) => Ok(())
+    fn bootstrap_cli(&self, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        if prompt::confirm("Encrypt the journal with a passphrase? (y/N)") {
+            Ok(Box::new(EncryptedFileJournal::bootstrap_cli()?))
+        } else {
+            Ok(Box::new(FileJournal::bootstrap_cli()?))
         }
     }
+
+    fn from_json(&self, journal: &Value, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        Ok(Box::new(FileJournal::from_json(journal)?))
+    }
+
+    fn from_options(&self, options: &JournalOptions, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        Ok(Box::new(FileJournal::create(&options.name, options.path()?)?))
+    }
 }
 
 impl PartialEq for FileJournal {
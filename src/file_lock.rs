@@ -0,0 +1,99 @@
+//! Advisory lock shared by every rjrn process writing to the same file.
+//! The lock is taken on `<path>.lock` rather than on the file itself, as the
+//! file is replaced by a new one on every save. It's released when dropped,
+//! or by the OS when the process dies, so a left over `.lock` file is harmless.
+//!
+//!```
+//!    use rjrn::file_lock::FileLock;
+//!    use std::path::Path;
+//!    use std::time::Duration;
+//!
+//!    let path = std::env::temp_dir().join("rjrn-file-lock-doc.json");
+//!    let lock = FileLock::acquire(&path, Duration::from_millis(100)).unwrap();
+//!    assert!(FileLock::acquire(&path, Duration::from_millis(100)).is_err());
+//!
+//!    drop(lock);
+//!    assert!(FileLock::acquire(&path, Duration::from_millis(100)).is_ok());
+//!```
+
+use fs2::FileExt;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_TIMEOUT_MS: u64 = 5000;
+const RETRY_INTERVAL_MS: u64 = 50;
+
+#[derive(Debug)]
+pub struct FileLock {
+    file: File,
+}
+
+impl FileLock {
+    // Waits up to `timeout` for other processes to finish with the file
+    pub fn acquire(path: &Path, timeout: Duration) -> Result<FileLock, String> {
+        let lock_path = lock_path(path);
+        let file = OpenOptions::new().write(true).create(true).truncate(false).open(&lock_path)
+                   .map_err(|why| format!("Couldn't open lock file {} because: {}",
+                                          lock_path.display(), why))?;
+
+        let started = Instant::now();
+        loop {
+            if file.try_lock_exclusive().is_ok() {
+                debug!("Locked {}", lock_path.display());
+                return Ok(FileLock { file });
+            }
+
+            if started.elapsed() >= timeout {
+                return Err(format!("Timed out after {} ms waiting for {}, another rjrn \
+                                    is still using it", duration_ms(timeout), path.display()));
+            }
+
+            thread::sleep(Duration::from_millis(RETRY_INTERVAL_MS));
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+pub fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+    name.push(".lock");
+    path.with_file_name(name)
+}
+
+fn duration_ms(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + duration.subsec_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use test_support::TempDir;
+    use super::{lock_path, FileLock};
+
+    #[test]
+    fn waits_for_the_lock_to_be_released() {
+        let dir = TempDir::new("file-lock");
+        fs::create_dir_all(dir.join("")).unwrap();
+        let path = dir.join("journal.json");
+        let lock = FileLock::acquire(&path, Duration::from_millis(100)).unwrap();
+        assert_eq!(lock_path(&path), dir.join("journal.json.lock"));
+
+        let started = Instant::now();
+        let holder = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            drop(lock);
+        });
+        assert!(FileLock::acquire(&path, Duration::from_secs(5)).is_ok());
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        holder.join().unwrap();
+    }
+}
@@ -0,0 +1,252 @@
+//! Reads and writes the small subset of YAML front matter used for entries:
+//! `key: value` lines between two `---` lines, followed by the body.
+//! Values are plain or double quoted strings, `null`, or lists of strings
+//! written as `[a, "b"]` or as `- a` lines below the key.
+//!
+//!```
+//!    use rjrn::front_matter::{self, Value};
+//!
+//!    let text = front_matter::render(&vec![
+//!        ("title".to_string(), Value::Scalar("Hello: world".to_string())),
+//!        ("tags".to_string(), Value::List(vec!["work".to_string()])),
+//!    ], "Body");
+//!
+//!    let (fields, body) = front_matter::parse(&text).unwrap();
+//!    assert_eq!(fields.get("title"), Some(&Value::Scalar("Hello: world".to_string())));
+//!    assert_eq!(fields.get("tags"), Some(&Value::List(vec!["work".to_string()])));
+//!    assert_eq!(body, "Body");
+//!```
+
+use serde_json;
+use std::collections::BTreeMap;
+
+const DELIMITER: &str = "---";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Scalar(String),
+    List(Vec<String>),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::Scalar(ref s) => Some(s),
+            _ => None
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self.as_str() {
+            Some("true") | Some("yes") => Some(true),
+            Some("false") | Some("no") => Some(false),
+            _ => None
+        }
+    }
+
+    // A single scalar counts as a list with one item
+    pub fn as_list(&self) -> Vec<String> {
+        match *self {
+            Value::Null => vec![],
+            Value::Scalar(ref s) => vec![s.clone()],
+            Value::List(ref items) => items.clone(),
+        }
+    }
+}
+
+pub fn render(fields: &Vec<(String, Value)>, body: &str) -> String {
+    let mut text = String::new();
+    text.push_str(DELIMITER);
+    text.push('\n');
+
+    for (key, value) in fields.iter() {
+        let value = match *value {
+            Value::Null => "null".to_string(),
+            Value::Scalar(ref s) => quote_if_needed(s),
+            Value::List(ref items) => format!("[{}]", items.iter()
+                                                .map(|i| quote_if_needed(i))
+                                                .collect::<Vec<String>>()
+                                                .join(", ")),
+        };
+        text.push_str(&format!("{}: {}\n", key, value));
+    }
+
+    text.push_str(DELIMITER);
+    text.push('\n');
+    text.push_str(body);
+    text.push('\n');
+    text
+}
+
+// Fields of the front matter and the body, with the trailing newline removed.
+// Text without front matter is returned as the body.
+pub fn parse(text: &str) -> Result<(BTreeMap<String, Value>, String), String> {
+    let mut fields: BTreeMap<String, Value> = BTreeMap::new();
+    // Split on '\n' only, so the length of the header is known even with "\r\n"
+    let mut lines = text.split('\n');
+
+    let mut header_len = match lines.next() {
+        Some(line) if line.trim_end() == DELIMITER => line.len() + 1,
+        _ => return Ok((fields, strip_newline(text)))
+    };
+
+    let mut list_key: Option<String> = None;
+    let mut closed = false;
+
+    for line in lines {
+        header_len += line.len() + 1;
+
+        if line.trim_end() == DELIMITER {
+            closed = true;
+            break;
+        }
+
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        if let Some(ref key) = list_key {
+            if line.trim_start().starts_with("- ") {
+                let item = unquote(line.trim_start()[2..].trim())?;
+                if let Some(&mut Value::List(ref mut items)) = fields.get_mut(key) {
+                    items.push(item);
+                }
+                continue;
+            }
+        }
+
+        let (key, value) = match line.find(':') {
+            Some(i) => (line[..i].trim().to_string(), line[i + 1..].trim()),
+            None => return Err(format!("Expected `key: value`, got: {}", line))
+        };
+
+        list_key = None;
+        let value = if value.is_empty() {
+            list_key = Some(key.clone());
+            Value::List(vec![])
+        } else if value == "null" || value == "~" {
+            Value::Null
+        } else if value.starts_with('[') && value.ends_with(']') {
+            Value::List(parse_list(&value[1..value.len() - 1])?)
+        } else {
+            Value::Scalar(unquote(value)?)
+        };
+        fields.insert(key, value);
+    }
+
+    if !closed {
+        return Err("Front matter isn't closed with `---`".to_string());
+    }
+
+    let body = if header_len >= text.len() { "" } else { &text[header_len..] };
+    Ok((fields, strip_newline(body)))
+}
+
+fn strip_newline(text: &str) -> String {
+    text.strip_suffix("\r\n")
+        .or_else(|| text.strip_suffix('\n'))
+        .unwrap_or(text)
+        .to_string()
+}
+
+// Ids, dates and single words are left plain, anything else is quoted.
+// JSON strings are valid YAML double quoted strings.
+fn quote_if_needed(s: &str) -> String {
+    let plain = s.chars().next().map(|c| c.is_alphanumeric()).unwrap_or(false) &&
+        s.chars().all(|c| c.is_alphanumeric() || "_.:+-".contains(c)) &&
+        !s.ends_with(':') && s != "null";
+
+    if plain { s.to_string() } else { serde_json::to_string(&s.to_string()).unwrap() }
+}
+
+fn unquote(value: &str) -> Result<String, String> {
+    if value.starts_with('"') {
+        serde_json::from_str::<String>(value)
+            .map_err(|_| format!("Couldn't parse quoted value: {}", value))
+    } else if value.starts_with('\'') && value.ends_with('\'') && value.len() > 1 {
+        Ok(value[1..value.len() - 1].replace("''", "'"))
+    } else {
+        Ok(value.to_string())
+    }
+}
+
+fn parse_list(items: &str) -> Result<Vec<String>, String> {
+    let mut result = vec![];
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut escaped = false;
+
+    for c in items.chars() {
+        if in_quotes {
+            current.push(c);
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_quotes = false;
+            }
+        } else if c == ',' {
+            result.push(unquote(current.trim())?);
+            current.clear();
+        } else {
+            if c == '"' {
+                in_quotes = true;
+            }
+            current.push(c);
+        }
+    }
+
+    if !current.trim().is_empty() {
+        result.push(unquote(current.trim())?);
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, render, Value};
+
+    #[test]
+    fn values_needing_quotes_round_trip() {
+        let fields = vec![
+            ("title".to_string(), Value::Scalar("Deploy: \"done\", finally".to_string())),
+            ("word".to_string(), Value::Scalar("null".to_string())),
+            ("empty".to_string(), Value::Null),
+            ("tags".to_string(), Value::List(vec!["work".to_string(), "a, b".to_string()])),
+        ];
+        let text = render(&fields, "First line\n\n---\nAfter a rule");
+
+        let (parsed, body) = parse(&text).unwrap();
+        assert_eq!(parsed.len(), 4);
+        for (key, value) in fields.iter() {
+            assert_eq!(parsed.get(key), Some(value), "{}", key);
+        }
+        assert_eq!(body, "First line\n\n---\nAfter a rule");
+    }
+
+    #[test]
+    fn hand_written_front_matter_is_read() {
+        let text = "---\r\n# written by hand\r\ntitle: 'It''s done'\r\ntags:\r\n  - work\r\n  - \"ci\"\r\n\
+                    starred: yes\r\nupdated_at: ~\r\n---\r\nBody\r\n";
+        let (fields, body) = parse(text).unwrap();
+        assert_eq!(fields.get("title").and_then(|t| t.as_str()), Some("It's done"));
+        assert_eq!(fields.get("tags").unwrap().as_list(), vec!["work", "ci"]);
+        assert_eq!(fields.get("starred").and_then(|s| s.as_bool()), Some(true));
+        assert_eq!(fields.get("updated_at"), Some(&Value::Null));
+        assert_eq!(body, "Body");
+    }
+
+    #[test]
+    fn text_without_front_matter_is_the_body() {
+        let (fields, body) = parse("Just a note\n").unwrap();
+        assert!(fields.is_empty());
+        assert_eq!(body, "Just a note");
+
+        assert!(parse("---\ntitle: never closed\nBody\n").is_err());
+        assert!(parse("---\nnot a field\n---\n").is_err());
+        assert_eq!(parse("---\ntitle: x\n---").unwrap().1, "");
+    }
+}
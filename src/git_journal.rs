@@ -0,0 +1,364 @@
+//! Git Journal wraps a journal of any other type kept inside a local git
+//! repository and commits the journal's files after every change, with a
+//! message made from the title of the entry. Those are its file or directory
+//! and the files rjrn keeps next to it, like the `.rjrn` of jrnl journals, but
+//! not locks and backups. Other files of the repository are never staged or
+//! committed. `git log`, `git diff` and `git blame`
+//! then show the history of the journal, and entries removed with `--undo`
+//! can be brought back from it.
+//!
+//! The wrapped journal is saved in the config under `journal`. Paths are
+//! used as they are, `~` isn't expanded in the config, `rjrn journal add`
+//! saves them absolute:
+//!
+//!```text
+//!    {"type": "GitJournal", "repository": "/home/me/notes",
+//!     "journal": {"type": "MarkdownDirJournal", "name": "notes", "path": "/home/me/notes/entries", ...}}
+//!```
+//!
+//! Usage for bootstraping and saving:
+//!
+//!```
+//!    use rjrn::journal::{Journal, JournalRegistry};
+//!    use rjrn::markdown_dir_journal::MarkdownDirJournal;
+//!    use rjrn::git_journal::GitJournal;
+//!
+//!    let registry = JournalRegistry::with_builtins();
+//!    let journal = GitJournal::new("notes", Box::new(MarkdownDirJournal::new("name", "notes/entries")));
+//!    assert_eq!(journal, GitJournal::from_json(&journal.to_json(), &registry).unwrap());
+//!```
+
+use serde_json::{Map, Value};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use uuid::Uuid;
+
+use journal::{Journal, JournalFactory, JournalOptions, JournalRegistry};
+use entry::Entry;
+use prompt;
+use query::Query;
+
+// Titles are cut so the subject of the commit stays on one short line
+const MAX_TITLE_LEN: usize = 60;
+// Locks and backups of the journal's files, also those in its directory
+const NOT_COMMITTED: [&str; 3] = [":(exclude)*.lock", ":(exclude)*.bak", ":(exclude)*.bak.*"];
+
+#[derive(Debug)]
+pub struct GitJournal {
+    repository: String,
+    inner: Box<dyn Journal>,
+}
+
+impl Journal for GitJournal {
+    fn to_json(&self) -> Value {
+        let mut d = Map::new();
+        d.insert("type".to_string(), Value::String("GitJournal".to_string()));
+        d.insert("repository".to_string(), Value::String(self.repository.clone()));
+        d.insert("journal".to_string(), self.inner.to_json());
+        Value::Object(d)
+    }
+
+    fn set_default(&mut self) {
+        self.inner.set_default()
+    }
+
+    fn is_default(&self) -> bool {
+        self.inner.is_default()
+    }
+
+    fn name(&self) -> &String {
+        self.inner.name()
+    }
+
+    fn entries(&self) -> Result<Vec<Box<Entry>>, String> {
+        self.inner.entries()
+    }
+
+    fn query_entries(&self, query: &Query) -> Result<Vec<Box<Entry>>, String> {
+        self.inner.query_entries(query)
+    }
+
+    fn upsert_entry(&self, entry: Entry) -> Result<Uuid, String> {
+        let message = format!("Save entry: {}", describe(&entry));
+        let id = self.inner.upsert_entry(entry)?;
+        self.commit(&message);
+        Ok(id)
+    }
+
+    fn remove_entries(&self, ids: &Vec<Uuid>) -> Result<(), String> {
+        let removed: Vec<String> = self.inner.entries()?
+            .iter()
+            .filter(|e| ids.contains(e.id()))
+            .map(|e| describe(e))
+            .collect();
+
+        self.inner.remove_entries(ids)?;
+        self.commit(&format!("Remove entries: {}", removed.join(", ")));
+        Ok(())
+    }
+
+    fn files(&self) -> Vec<PathBuf> {
+        self.inner.files()
+    }
+
+    fn seal(&self, text: &str) -> Result<String, String> {
+        self.inner.seal(text)
+    }
+
+    fn unseal(&self, text: &str) -> Result<String, String> {
+        self.inner.unseal(text)
+    }
+
+    fn compact(&self) -> Result<(), String> {
+        self.inner.compact()?;
+        self.commit(&format!("Compact journal {}", self.inner.name()));
+        Ok(())
+    }
+}
+
+impl GitJournal {
+    pub fn new(repository: &str, inner: Box<dyn Journal>) -> GitJournal {
+        GitJournal {
+            repository: repository.to_string(),
+            inner,
+        }
+    }
+
+    pub fn from_json(j: &Value, registry: &JournalRegistry) -> Result<GitJournal, String> {
+        let repository = match j.get("repository").and_then(|r| r.as_str()) {
+            Some(repository) => repository,
+            None => return Err("Git journal config is missing its repository".to_string())
+        };
+
+        match j.get("journal") {
+            Some(inner) => Ok(GitJournal::new(repository, registry.from_json(inner)?)),
+            None => Err("Git journal config is missing its journal".to_string())
+        }
+    }
+
+    // Used by the CLI when the user want to create a new journal of that type
+    pub fn bootstrap_cli(registry: &JournalRegistry) -> Result<GitJournal, String> {
+        let repository = prompt::read_line("Path of the git repository (created if missing):");
+        if repository.is_empty() {
+            return Err("The git repository is required".to_string());
+        }
+
+        init_repository(&repository)?;
+
+        println!("Now the journal to keep in the repository, put its files inside {}.", repository);
+        let inner = registry.bootstrap_cli()?;
+        Ok(GitJournal::new(&repository, inner))
+    }
+
+    // The change is already saved when this runs, a failed commit is only a
+    // warning so the change isn't made twice by trying again
+    fn commit(&self, message: &str) {
+        if let Err(why) = self.commit_journal(message) {
+            warn!("The change is saved, but couldn't be committed to {}: {}", self.repository, why);
+        }
+    }
+
+    // Only the journal's own files are staged and committed, other changes in
+    // the repository are left alone. Nothing is committed when they didn't change.
+    fn commit_journal(&self, message: &str) -> Result<(), String> {
+        let paths = self.journal_paths();
+        if paths.is_empty() {
+            debug!("Journal {} has no files to commit", self.inner.name());
+            return Ok(());
+        }
+
+        let pathspec: Vec<&str> = paths.iter().map(|p| p.as_str()).chain(NOT_COMMITTED.iter().cloned()).collect();
+        let with_pathspec = |args: &[&str]| -> Vec<String> {
+            args.iter().chain(["--"].iter()).chain(pathspec.iter()).map(|a| a.to_string()).collect()
+        };
+
+        git(&self.repository, &with_pathspec(&["add", "-A"]))?;
+
+        if git(&self.repository, &with_pathspec(&["diff", "--cached", "--name-only"]))?.trim().is_empty() {
+            debug!("Nothing to commit in {}", self.repository);
+            return Ok(());
+        }
+
+        debug!("Committing {} with message: {}", paths.join(", "), message);
+        git(&self.repository, &with_pathspec(&["commit", "-q", "-m", message])).map(|_| ())
+    }
+
+    // File or directory of the wrapped journal, so removed files are staged too,
+    // and the files it keeps next to it. Absolute as git runs in the repository.
+    fn journal_paths(&self) -> Vec<String> {
+        let json = self.inner.to_json();
+        let mut paths: Vec<PathBuf> = json.get("path").and_then(|p| p.as_str())
+            .map(|path| vec![absolute(Path::new(path))])
+            .unwrap_or(vec![]);
+
+        for file in self.inner.files().iter().map(|f| absolute(f)) {
+            // Explicit paths git doesn't know would fail the commit
+            let name = file.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            let ignored = name.ends_with(".lock") || name.ends_with(".bak") || name.contains(".bak.");
+            if !ignored && !paths.iter().any(|p| file.starts_with(p)) {
+                paths.push(file);
+            }
+        }
+
+        paths.iter().filter_map(|p| p.to_str()).map(|p| p.to_string()).collect()
+    }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir().map(|dir| dir.join(path)).unwrap_or(path.to_path_buf())
+    }
+}
+
+// Output of the git command, or its error output when it fails
+fn git<S: AsRef<str>>(repository: &str, args: &[S]) -> Result<String, String> {
+    let output = Command::new("git").arg("-C").arg(repository).args(args.iter().map(|a| a.as_ref())).output()
+                 .map_err(|why| format!("Couldn't run git: {}", why))?;
+
+    if !output.status.success() {
+        return Err(format!("git {} failed: {}", args[0].as_ref(),
+                           String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn describe(entry: &Entry) -> String {
+    let title = match *entry.title() {
+        Some(ref title) if !title.trim().is_empty() => title.trim().to_string(),
+        _ => return entry.id().hyphenated().to_string()
+    };
+
+    if title.chars().count() > MAX_TITLE_LEN {
+        format!("{}...", title.chars().take(MAX_TITLE_LEN).collect::<String>())
+    } else {
+        title
+    }
+}
+
+fn init_repository(repository: &str) -> Result<(), String> {
+    if Path::new(repository).join(".git").exists() {
+        return Ok(());
+    }
+
+    fs::create_dir_all(repository)
+    .map_err(|why| format!("Couldn't create the repository directory because: {}",
+                           why))?;
+    git(repository, &["init", "-q"]).map(|_| ())
+}
+
+pub struct GitJournalFactory;
+
+impl JournalFactory for GitJournalFactory {
+    fn kind(&self) -> &'static str {
+        "GitJournal"
+    }
+
+    fn description(&self) -> &'static str {
+        "Git Journal (any journal, committed to git on every change)"
+    }
+
+    fn bootstrap_cli(&self, registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        Ok(Box::new(GitJournal::bootstrap_cli(registry)?))
+    }
+
+    fn from_json(&self, journal: &Value, registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        Ok(Box::new(GitJournal::from_json(journal, registry)?))
+    }
+
+    fn wraps_journals(&self) -> bool {
+        true
+    }
+
+    // `repository` and the `journal` type to keep in it, which gets the other options
+    fn from_options(&self, options: &JournalOptions, registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        let repository = options.require("repository")?;
+        let kind = options.require("journal")?;
+        // It would get the same options and wrap itself forever
+        if registry.find_by_type(kind).map(|f| f.wraps_journals()).unwrap_or(false) {
+            return Err(format!("A git journal can't keep a {} journal, give the type of the journal \
+                                keeping the entries with --option journal=<type>", kind));
+        }
+        init_repository(repository)?;
+
+        let inner = registry.from_options(kind, options)?;
+        Ok(Box::new(GitJournal::new(repository, inner)))
+    }
+}
+
+impl PartialEq for GitJournal {
+     fn eq(&self, other: &GitJournal) -> bool {
+         self.repository == other.repository && self.inner.to_json() == other.inner.to_json()
+     }
+ }
+
+#[cfg(test)]
+mod tests {
+    use journal::Journal;
+    use jrnl_journal::JrnlJournal;
+    use markdown_dir_journal::MarkdownDirJournal;
+    use std::fs;
+    use test_support::{entry, TempDir};
+    use super::{git, init_repository, GitJournal};
+
+    // Repository in `dir` with an author for the commits, none without git
+    fn repository(dir: &TempDir) -> Option<String> {
+        let repository = dir.join("repo").to_string_lossy().into_owned();
+        init_repository(&repository).ok()?;
+        git(&repository, &["config", "user.name", "rjrn"]).unwrap();
+        git(&repository, &["config", "user.email", "rjrn@example.com"]).unwrap();
+        Some(repository)
+    }
+
+    fn committed(repository: &str) -> Vec<String> {
+        let mut files: Vec<String> = git(repository, &["ls-files"]).unwrap().lines().map(|l| l.to_string()).collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn files_next_to_the_journal_are_committed_without_locks_and_backups() {
+        let dir = TempDir::new("git");
+        let repository = match repository(&dir) {
+            Some(repository) => repository,
+            None => return
+        };
+        fs::write(dir.join("repo/other.txt"), "Not the journal's").unwrap();
+
+        let path = dir.join("repo/journal.txt").to_string_lossy().into_owned();
+        let journal = GitJournal::new(&repository, Box::new(JrnlJournal::new("notes", &path)));
+        journal.upsert_entry(entry("Milk.")).unwrap();
+        journal.upsert_entry(entry("Bread.")).unwrap();
+
+        assert!(dir.join("repo/journal.txt.bak").exists());
+        assert_eq!(committed(&repository), ["journal.txt", "journal.txt.rjrn"]);
+        let status = git(&repository, &["status", "--porcelain", "--untracked-files=no"]).unwrap();
+        assert!(status.is_empty(), "{}", status);
+        assert_eq!(git(&repository, &["log", "--format=%s"]).unwrap().lines().next(), Some("Save entry: Bread"));
+    }
+
+    #[test]
+    fn removed_files_of_a_directory_are_committed() {
+        let dir = TempDir::new("git");
+        let repository = match repository(&dir) {
+            Some(repository) => repository,
+            None => return
+        };
+
+        let path = dir.join("repo/entries").to_string_lossy().into_owned();
+        let journal = GitJournal::new(&repository, Box::new(MarkdownDirJournal::new("notes", &path)));
+        let milk = entry("Milk");
+        journal.upsert_entry(milk.clone()).unwrap();
+        journal.upsert_entry(entry("Bread")).unwrap();
+        journal.remove_entries(&vec![*milk.id()]).unwrap();
+
+        let files = committed(&repository);
+        assert_eq!(files.len(), 1, "{:?}", files);
+        assert!(files[0].starts_with("entries/") && files[0].ends_with(".md"));
+        assert!(git(&repository, &["status", "--porcelain", "--untracked-files=no"]).unwrap().is_empty());
+    }
+}
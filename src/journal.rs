@@ -1,17 +1,317 @@
-use rustc_serialize::json::Json;
-use entry::{Entry};
+//! Journal trait implemented by every backend and the registry of backend
+//! factories used by `Config` to build journals from their `type` string.
+//!
+//! Registering a custom backend:
+//!
+//!```
+//!    use rjrn::config::Config;
+//!    use rjrn::journal::JournalRegistry;
+//!    use rjrn::file_journal::FileJournalFactory;
+//!
+//!    let mut registry = JournalRegistry::new();
+//!    registry.register(Box::new(FileJournalFactory));
+//!    assert!(registry.find("FileJournal").is_some());
+//!
+//!    let config = Config::with_registry(registry);
+//!    assert!(config.journals.is_empty());
+//!```
+//!
+//! Adding a journal without prompts, `--type` can be the short name of the type:
+//!
+//!```
+//!    use rjrn::journal::{JournalOptions, JournalRegistry};
+//!
+//!    let registry = JournalRegistry::with_builtins();
+//!    let mut options = JournalOptions::new("work");
+//!    options.path = Some("/tmp/rjrn-work.json".to_string());
+//!
+//!    let journal = registry.from_options("file", &options).unwrap();
+//!    assert_eq!(journal.name(), "work");
+//!    assert!(registry.from_options("trello", &options).is_err());
+//!```
+
+use serde_json::Value;
+use chrono::Utc;
+use entry::{Entry, EntryBuilder, normalize_tag};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use atomic_write;
+use file_lock;
+use file_journal::FileJournalFactory;
+use encrypted_file_journal::EncryptedFileJournalFactory;
+use jsonl_journal::JsonlJournalFactory;
+use sqlite_journal::SqliteJournalFactory;
+use markdown_dir_journal::MarkdownDirJournalFactory;
+use jrnl_journal::JrnlJournalFactory;
+use trello_journal::TrelloJournalFactory;
+use git_journal::GitJournalFactory;
+use query::Query;
 use uuid::Uuid;
-use std::cmp::PartialEq;
+use std::fmt;
+use std::io;
 
-pub trait Journal : PartialEq {
-    fn bootstrap_cli() -> Result<Self, String>;
-    fn from_json(journal: &Json) -> Self;
-    fn to_json(&self) -> Json;
+pub trait Journal : fmt::Debug {
+    fn to_json(&self) -> Value;
     fn set_default(&mut self);
     fn is_default(&self) -> bool;
     fn name(&self) -> &String;
     fn entries(&self) -> Result<Vec<Box<Entry>>, String>;
-    fn upsert_entry(&self, entry: Entry) -> Result<(), String>;
+    // Returns the id the entry is saved with, backends without their own ids
+    // keep the one of the entry. The version it replaces is kept as a revision
+    // by every backend but Trello, see `Entry::keep_history_of`.
+    fn upsert_entry(&self, entry: Entry) -> Result<Uuid, String>;
     fn remove_entries(&self, ids: &Vec<Uuid>) -> Result<(), String>;
-    fn undo_last_entry(&self) -> Result<(), String>;
+
+    // Entries matching the query, in the order of `entries`. Backends with
+    // indexes only read the entries that could match.
+    fn query_entries(&self, query: &Query) -> Result<Vec<Box<Entry>>, String> {
+        Ok(self.entries()?
+            .into_iter()
+            .filter(|e| query.matches(e, self.name()))
+            .collect())
+    }
+
+    // Text kept outside of the journal about its entries, like its trash, is
+    // sealed so it's as protected as the journal. Plain journals keep it as is.
+    fn seal(&self, text: &str) -> Result<String, String> {
+        Ok(text.to_string())
+    }
+
+    fn unseal(&self, text: &str) -> Result<String, String> {
+        Ok(text.to_string())
+    }
+
+    // Files keeping the entries and the ones rjrn keeps next to them, like
+    // backups and locks. Only existing files, journals kept elsewhere have none.
+    fn files(&self) -> Vec<PathBuf> {
+        vec![]
+    }
+
+    // Reclaims space taken by old revisions, backends without them have nothing to do
+    fn compact(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    // Every tag with the number of entries having it, sorted by name
+    fn tag_counts(&self) -> Result<Vec<(String, usize)>, String> {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for entry in self.entries()?.iter() {
+            for tag in entry.tags().iter() {
+                *counts.entry(tag.clone()).or_insert(0) += 1;
+            }
+        }
+
+        Ok(counts.into_iter().collect())
+    }
+
+    // Replaces the `from` tags with `into` on every entry having one of them,
+    // returns how many entries changed
+    fn merge_tags(&self, from: &Vec<String>, into: &str) -> Result<usize, String> {
+        let from: Vec<String> = from.iter().map(|t| normalize_tag(t)).collect();
+        let mut changed = 0;
+
+        for entry in self.entries()?.iter() {
+            if !entry.tags().iter().any(|t| from.contains(t)) {
+                continue;
+            }
+
+            let mut builder = EntryBuilder::from_entry(entry);
+            builder.tags(vec![]).updated_at(Utc::now());
+            for tag in entry.tags().iter() {
+                builder.tag(if from.contains(tag) { into } else { tag });
+            }
+
+            self.upsert_entry(builder.finalize()?)?;
+            changed += 1;
+        }
+
+        Ok(changed)
+    }
+}
+
+/// Creates journals of one backend type, either from the saved config or
+/// interactively from the CLI.
+pub trait JournalFactory {
+    /// Value of the `type` key in the journal's JSON config.
+    fn kind(&self) -> &'static str;
+    /// Name shown in the CLI menu when adding a new journal.
+    fn description(&self) -> &'static str;
+    /// The registry is there for journals wrapping journals of other types.
+    fn bootstrap_cli(&self, registry: &JournalRegistry) -> Result<Box<dyn Journal>, String>;
+    fn from_json(&self, journal: &Value, registry: &JournalRegistry) -> Result<Box<dyn Journal>, String>;
+    /// Journals of this type wrap a journal of another type, like git journals.
+    fn wraps_journals(&self) -> bool {
+        false
+    }
+    /// Creates the journal from command line options, without prompts.
+    fn from_options(&self, _options: &JournalOptions, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        Err(format!("{} can only be added with `rjrn --add`", self.description()))
+    }
+}
+
+/// Settings of a new journal given on the command line. Backends keeping a
+/// file need `path`, the others take their own settings, like `list_id` of
+/// Trello journals.
+#[derive(Debug, Clone)]
+pub struct JournalOptions {
+    pub name: String,
+    pub path: Option<String>,
+    pub settings: BTreeMap<String, String>,
+}
+
+impl JournalOptions {
+    pub fn new(name: &str) -> JournalOptions {
+        JournalOptions { name: name.to_string(), path: None, settings: BTreeMap::new() }
+    }
+
+    pub fn path(&self) -> Result<&String, String> {
+        self.path.as_ref().ok_or(format!("Please give the path of the journal {} with --path", self.name))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&String> {
+        self.settings.get(key)
+    }
+
+    pub fn require(&self, key: &str) -> Result<&String, String> {
+        self.get(key).ok_or(format!("Please give {} of the journal with --option {}=<value>", key, key))
+    }
+}
+
+/// Short name of a journal type used by `--type`, `markdown-dir` for `MarkdownDirJournal`.
+pub fn short_type(kind: &str) -> String {
+    let mut name = String::new();
+    for (i, c) in kind.trim_end_matches("Journal").chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            name.push('-');
+        }
+        name.extend(c.to_lowercase());
+    }
+    name
+}
+
+/// Journal file with its backups, its lock and the files named `<path><suffix>`
+/// next to it with their backups, those that exist. For `Journal::files` of
+/// backends with one file.
+pub fn file_with_siblings(path: &str, suffixes: &[&str]) -> Vec<PathBuf> {
+    let path = Path::new(path);
+    let mut files = vec![path.to_path_buf(), file_lock::lock_path(path)];
+    files.extend((0..atomic_write::BACKUPS).map(|n| atomic_write::backup_path(path, n)));
+    for suffix in suffixes.iter() {
+        let sibling = PathBuf::from(format!("{}{}", path.display(), suffix));
+        files.extend((0..atomic_write::BACKUPS).map(|n| atomic_write::backup_path(&sibling, n)));
+        files.push(sibling);
+    }
+
+    files.into_iter().filter(|f| f.exists()).collect()
+}
+
+/// String setting of a journal's JSON config, for `from_json` of the backends.
+pub fn config_string(j: &Value, key: &str) -> Result<String, String> {
+    j.get(key)
+        .and_then(|v| v.as_str())
+        .map(|v| v.to_string())
+        .ok_or(format!("Journal config is missing its {}", key))
+}
+
+#[derive(Default)]
+pub struct JournalRegistry {
+    factories: Vec<Box<dyn JournalFactory>>
+}
+
+impl JournalRegistry {
+    /// Registry without any backends.
+    pub fn new() -> JournalRegistry {
+        JournalRegistry { factories: vec![] }
+    }
+
+    /// Registry with all backends shipped with rjrn.
+    pub fn with_builtins() -> JournalRegistry {
+        let mut registry = JournalRegistry::new();
+        registry.register(Box::new(FileJournalFactory));
+        registry.register(Box::new(EncryptedFileJournalFactory));
+        registry.register(Box::new(JsonlJournalFactory));
+        registry.register(Box::new(SqliteJournalFactory));
+        registry.register(Box::new(MarkdownDirJournalFactory));
+        registry.register(Box::new(JrnlJournalFactory));
+        registry.register(Box::new(TrelloJournalFactory));
+        registry.register(Box::new(GitJournalFactory));
+        registry
+    }
+
+    /// Adds a backend. A factory with the same `kind` replaces the old one.
+    pub fn register(&mut self, factory: Box<dyn JournalFactory>) {
+        match self.factories.iter().position(|f| f.kind() == factory.kind()) {
+            Some(i) => self.factories[i] = factory,
+            None => self.factories.push(factory)
+        }
+    }
+
+    pub fn find(&self, kind: &str) -> Option<&Box<dyn JournalFactory>> {
+        self.factories.iter().find(|f| f.kind() == kind)
+    }
+
+    /// Factory for the full `kind` or its short name.
+    pub fn find_by_type(&self, name: &str) -> Option<&Box<dyn JournalFactory>> {
+        self.factories.iter().find(|f| f.kind() == name || short_type(f.kind()) == name)
+    }
+
+    pub fn factories(&self) -> &Vec<Box<dyn JournalFactory>> {
+        &self.factories
+    }
+
+    pub fn from_json(&self, journal: &Value) -> Result<Box<dyn Journal>, String> {
+        let kind = match journal.get("type").and_then(|t| t.as_str()) {
+            Some(kind) => kind,
+            None => return Err("Journal config is missing its type".to_string())
+        };
+
+        match self.find(kind) {
+            Some(factory) => factory.from_json(journal, self),
+            None => Err(format!("Unknown journal type: {}", kind))
+        }
+    }
+
+    pub fn from_options(&self, kind: &str, options: &JournalOptions) -> Result<Box<dyn Journal>, String> {
+        if options.name.trim().is_empty() {
+            return Err("Please give the name of the journal with --name".to_string());
+        }
+
+        match self.find_by_type(kind) {
+            Some(factory) => factory.from_options(options, self),
+            None => {
+                let types: Vec<String> = self.factories.iter().map(|f| short_type(f.kind())).collect();
+                Err(format!("Unknown journal type: {}, expected one of {}", kind, types.join(", ")))
+            }
+        }
+    }
+
+    // Asks for the type of the journal and lets its factory ask for the rest
+    pub fn bootstrap_cli(&self) -> Result<Box<dyn Journal>, String> {
+        println!("What type of journal you'd like to add: ");
+        for (i, factory) in self.factories.iter().enumerate() {
+            println!("({}) {}", i + 1, factory.description());
+        }
+        println!("(??) TODO: Dropbox Journal");
+
+        loop {
+            let mut selected_option = String::new();
+            io::stdin().read_line(&mut selected_option).expect("Failed to parse selection");
+
+            debug!("Selected option: {}", selected_option);
+            let selected = selected_option.trim().parse::<usize>().ok()
+                .and_then(|i| if i > 0 { self.factories.get(i - 1) } else { None });
+
+            match selected {
+                Some(factory) => return factory.bootstrap_cli(self),
+                None => println!("Failed to parse selection. Please try again:")
+            }
+        }
+    }
+}
+
+impl fmt::Debug for JournalRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kinds: Vec<&str> = self.factories.iter().map(|f| f.kind()).collect();
+        write!(f, "JournalRegistry {:?}", kinds)
+    }
 }
@@ -0,0 +1,600 @@
+//! Jrnl Journal reads and writes the plain text format of jrnl, so a journal
+//! can be shared with it:
+//!
+//!```text
+//!    [2026-01-01 09:30] Fixed the build. It was the cache again @work *
+//!
+//!    [2026-01-02 18:00] Evening run.
+//!```
+//!
+//! The first sentence is the title, a `*` at the end of the first line stars
+//! the entry and `@tags` anywhere in the text become the entry's tags.
+//!
+//! jrnl has no ids, so entries are known by their date and title. rjrn keeps
+//! the id and the tags it gave every entry in `<path>.rjrn` next to the file,
+//! so ids stay the same when the title is edited and removed tags stay removed.
+//! Earlier versions of the entries are kept there too.
+//! Entries added or changed by jrnl itself get an id derived from their date
+//! and title and the `@tags` of their text.
+//!
+//! Both files are replaced atomically under a lock on `<path>.lock`, like the
+//! file of `FileJournal`.
+//!
+//! Usage for bootstraping and saving:
+//!
+//!```
+//!    use rjrn::journal::Journal;
+//!    use rjrn::jrnl_journal::JrnlJournal;
+//!    let journal = JrnlJournal::new("name", "journal.txt");
+//!    assert_eq!(journal, JrnlJournal::from_json(&journal.to_json()).unwrap());
+//!```
+
+use serde_json::{self, Map, Value};
+use chrono::*;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use uuid::Uuid;
+
+use journal::{config_string, file_with_siblings, Journal, JournalFactory, JournalOptions, JournalRegistry};
+use entry::{Entry, EntryBuilder, Revision};
+use prompt;
+use atomic_write;
+use file_lock::{self, FileLock};
+
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M";
+// `[YYYY-MM-DD HH:MM]`
+const DATE_LEN: usize = 18;
+const TITLE_DIVIDERS: &str = ".?!";
+
+#[derive(Debug)]
+pub struct JrnlJournal {
+    name: String,
+    path: String,
+    default: bool,
+    // How long to wait for other rjrn processes writing to the same file
+    lock_timeout_ms: u64,
+}
+
+impl Journal for JrnlJournal {
+    fn to_json(&self) -> Value {
+        let mut d = Map::new();
+        d.insert("name".to_string(), Value::String(self.name.clone()));
+        d.insert("path".to_string(), Value::String(self.path.clone()));
+        d.insert("type".to_string(), Value::String("JrnlJournal".to_string()));
+        d.insert("default".to_string(), Value::Bool(self.default));
+        d.insert("lock_timeout_ms".to_string(), Value::from(self.lock_timeout_ms));
+        Value::Object(d)
+    }
+
+    fn set_default(&mut self) {
+        self.default = true
+    }
+
+    fn is_default(&self) -> bool {
+        self.default
+    }
+
+    fn name(&self) -> &String {
+        &self.name
+    }
+
+    fn entries(&self) -> Result<Vec<Box<Entry>>, String> {
+        let s = match read_file(&self.path)? {
+            Some(s) => s,
+            None => return Ok(vec![])
+        };
+
+        let known = self.known_entries()?;
+        Ok(parse(&s).into_iter().map(|e| with_known(e, &known)).collect())
+    }
+
+    fn upsert_entry(&self, entry: Entry) -> Result<Uuid, String> {
+        debug!("Add entry with title:: {:?} to - {:?}", entry.title(), self.path);
+        let id = *entry.id();
+        let _lock = self.lock()?;
+        let mut entries = self.entries()?;
+
+        let mut entry = entry;
+        match entries.iter().position(|e| e.id() == entry.id()) {
+            Some(i) => {
+                entry.keep_history_of(&entries[i]);
+                *entries[i] = entry;
+            },
+            None => entries.push(Box::new(entry))
+        }
+
+        self.save_entries(&entries)?;
+        Ok(id)
+    }
+
+    fn remove_entries(&self, ids: &Vec<Uuid>) -> Result<(), String> {
+        let _lock = self.lock()?;
+        self.save_entries(
+            &self.entries()?
+                  .into_iter()
+                  .filter(|e| !ids.contains(e.id()))
+                  .collect())
+    }
+
+    fn files(&self) -> Vec<PathBuf> {
+        file_with_siblings(&self.path, &[".rjrn"])
+    }
+
+    fn compact(&self) -> Result<(), String> {
+        let _lock = self.lock()?;
+        let mut entries = self.entries()?;
+        for entry in entries.iter_mut() {
+            entry.forget_revisions();
+        }
+        self.save_entries(&entries)
+    }
+}
+
+impl JrnlJournal {
+    pub fn new(name: &str, path: &str) -> JrnlJournal {
+        JrnlJournal {
+            name: name.to_string(),
+            path: path.to_string(),
+            default: false,
+            lock_timeout_ms: file_lock::DEFAULT_TIMEOUT_MS,
+        }
+    }
+
+    pub fn from_json(j: &Value) -> Result<JrnlJournal, String> {
+        Ok(JrnlJournal {
+            name: config_string(j, "name")?,
+            path: config_string(j, "path")?,
+            default: j.get("default").and_then(|d| d.as_bool()).unwrap_or(false),
+            lock_timeout_ms: j.get("lock_timeout_ms").and_then(|t| t.as_u64())
+                .unwrap_or(file_lock::DEFAULT_TIMEOUT_MS),
+        })
+    }
+
+    // Used by the CLI when the user want to create a new journal of that type
+    pub fn bootstrap_cli() -> Result<JrnlJournal, String> {
+        let name = prompt::journal_name();
+        let path = prompt::journal_path(&format!("rjrn-{}.txt", name))?;
+
+        Ok(JrnlJournal::new(&name, &path))
+    }
+
+    // `<path>.rjrn`
+    pub fn known_path(&self) -> String {
+        format!("{}.rjrn", self.path)
+    }
+
+    // Held while entries are read, changed and saved, so no other process saves in between
+    fn lock(&self) -> Result<FileLock, String> {
+        FileLock::acquire(Path::new(&self.path), Duration::from_millis(self.lock_timeout_ms))
+    }
+
+    fn known_entries(&self) -> Result<Vec<Known>, String> {
+        match read_file(&self.known_path())? {
+            Some(ref s) if !s.trim().is_empty() => serde_json::from_str(s)
+                .map_err(|why| format!("Couldn't read {} because: {}", self.known_path(), why)),
+            _ => Ok(vec![])
+        }
+    }
+
+    // jrnl keeps entries sorted by date. Every entry is read back the way it
+    // was written, to know it by its date and title next time.
+    fn save_entries(&self, entries: &Vec<Box<Entry>>) -> Result<(), String> {
+        let mut entries: Vec<&Box<Entry>> = entries.iter().collect();
+        entries.sort_by_key(|a| a.created_at());
+
+        let mut chunks = vec![];
+        let mut known = vec![];
+        let mut written: Vec<Box<Entry>> = vec![];
+        for entry in entries.iter() {
+            let chunk = to_jrnl(entry);
+            let before = written.len();
+            parse_into(&chunk, &mut written);
+            if written.len() > before {
+                let read_back = &written[before];
+                known.push(Known {
+                    key: *read_back.id(),
+                    id: *entry.id(),
+                    tags: entry.tags().clone(),
+                    text: derive_id(read_back.content()),
+                    revisions: entry.revisions().to_vec(),
+                });
+            }
+            chunks.push(chunk);
+        }
+
+        write_file(&self.path, &chunks.join("\n"))?;
+        write_file(&self.known_path(), &serde_json::to_string(&known).unwrap())
+    }
+}
+
+// What rjrn knows about an entry that the jrnl format can't keep. `key` is the
+// id derived from the entry's date and title, `text` the one from its text,
+// to notice changes made outside of rjrn.
+#[derive(Deserialize, Serialize)]
+#[derive(Debug)]
+struct Known {
+    key: Uuid,
+    id: Uuid,
+    tags: Vec<String>,
+    text: Uuid,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    revisions: Vec<Revision>,
+}
+
+// The id rjrn gave the entry and its revisions, and its tags unless its text
+// was changed by jrnl
+fn with_known(entry: Box<Entry>, known: &Vec<Known>) -> Box<Entry> {
+    let known = match known.iter().find(|k| k.key == *entry.id()) {
+        Some(known) => known,
+        None => return entry
+    };
+
+    let mut builder = EntryBuilder::from_entry(&entry);
+    builder.id(known.id);
+    if known.text == derive_id(entry.content()) {
+        builder.tags(known.tags.clone());
+    }
+
+    match builder.finalize() {
+        Ok(mut known_entry) => {
+            known_entry.set_revisions(known.revisions.clone());
+            Box::new(known_entry)
+        },
+        Err(_) => entry
+    }
+}
+
+fn read_file(path: &str) -> Result<Option<String>, String> {
+    let mut s = String::new();
+    match File::open(path) {
+        Ok(mut file) => file.read_to_string(&mut s)
+                        .map_err(|why| format!("Couldn't read entries {}",
+                                               why))?,
+        Err(_) => return Ok(None)
+    };
+
+    Ok(Some(s))
+}
+
+fn write_file(path: &str, text: &str) -> Result<(), String> {
+    atomic_write::write(Path::new(path), text.as_bytes())
+        .map_err(|why| format!("Couldn't save the journal file because: {}", why))
+}
+
+fn parse(text: &str) -> Vec<Box<Entry>> {
+    let mut entries = vec![];
+    parse_into(text, &mut entries);
+    entries
+}
+
+// Entries before the text count for the ids of entries with the same date and title
+fn parse_into(text: &str, entries: &mut Vec<Box<Entry>>) {
+    let mut current: Option<(DateTime<Utc>, Vec<&str>)> = None;
+
+    for line in text.lines() {
+        match parse_date(line) {
+            Some(date) => {
+                if let Some((date, lines)) = current.take() {
+                    push_entry(entries, date, &lines);
+                }
+                current = Some((date, vec![line[DATE_LEN..].trim_start()]));
+            },
+            None => match current {
+                Some((_, ref mut lines)) => lines.push(line),
+                None => if !line.trim().is_empty() {
+                    warn!("Skipping text before the first jrnl entry: {}", line);
+                }
+            }
+        }
+    }
+
+    if let Some((date, lines)) = current.take() {
+        push_entry(entries, date, &lines);
+    }
+}
+
+fn push_entry(entries: &mut Vec<Box<Entry>>, date: DateTime<Utc>, lines: &Vec<&str>) {
+    match to_entry(date, lines, entries) {
+        Ok(entry) => entries.push(Box::new(entry)),
+        Err(why) => warn!("Skipping jrnl entry from {}: {}", date, why)
+    }
+}
+
+fn parse_date(line: &str) -> Option<DateTime<Utc>> {
+    if line.len() < DATE_LEN || !line.starts_with('[') || !line.is_char_boundary(DATE_LEN) ||
+        &line[DATE_LEN - 1..DATE_LEN] != "]" {
+        return None;
+    }
+
+    NaiveDateTime::parse_from_str(&line[1..DATE_LEN - 1], DATE_FORMAT).ok()
+        .and_then(|date| Local.from_local_datetime(&date).earliest())
+        .map(|date| date.with_timezone(&Utc))
+}
+
+fn to_entry(date: DateTime<Utc>, lines: &Vec<&str>, previous: &Vec<Box<Entry>>) -> Result<Entry, String> {
+    let mut lines: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+    while lines.last().map(|l| l.trim().is_empty()).unwrap_or(false) {
+        lines.pop();
+    }
+    if lines.is_empty() {
+        return Err("Content is empty!".to_string());
+    }
+
+    let starred = lines[0].trim_end().ends_with('*');
+    if starred {
+        let first = lines[0].trim_end();
+        lines[0] = first[..first.len() - 1].trim_end().to_string();
+    }
+
+    let content = lines.join("\n");
+    let title = title_of(&content);
+
+    // Entries with the same date and title get a different id by their order
+    let duplicates = previous.iter()
+        .filter(|e| e.created_at() == date && e.title().as_ref() == Some(&title))
+        .count();
+
+    let mut builder = EntryBuilder::new();
+    builder.id(derive_id(&format!("{}\n{}\n{}", date.to_rfc3339(), title, duplicates)))
+        .title(title)
+        .raw_content(content.clone())
+        .created_at(date)
+        .updated_at(date)
+        .starred(starred)
+        .tags(tags_of(&content));
+
+    builder.finalize()
+}
+
+fn to_jrnl(entry: &Entry) -> String {
+    let mut content = entry.content().clone();
+
+    if let Some(ref title) = *entry.title() {
+        if !title.is_empty() && !content.starts_with(title.as_str()) {
+            let divider = if title.ends_with(|c: char| TITLE_DIVIDERS.contains(c)) { "" } else { "." };
+            content = format!("{}{} {}", title, divider, content);
+        }
+    }
+
+    // Tags only set on the entry wouldn't survive reading the file back
+    let in_text = tags_of(&content);
+    let missing: Vec<String> = entry.tags().iter()
+        .filter(|t| !in_text.contains(t))
+        .map(|t| format!("@{}", t))
+        .collect();
+    if !missing.is_empty() {
+        content = format!("{} {}", content.trim_end(), missing.join(" "));
+    }
+
+    let mut lines: Vec<String> = content.lines().map(|l| l.to_string()).collect();
+    if lines.is_empty() {
+        lines.push(String::new());
+    }
+    if entry.starred() {
+        lines[0] = format!("{} *", lines[0].trim_end());
+    }
+
+    let date = entry.created_at().with_timezone(&Local).format(DATE_FORMAT);
+    format!("[{}] {}\n", date, lines.join("\n"))
+}
+
+// First sentence, like `EntryBuilder` does for new entries
+fn title_of(content: &str) -> String {
+    let mut chars = content.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        if c == '\n' {
+            return content[..i].trim().to_string();
+        }
+
+        if TITLE_DIVIDERS.contains(c) {
+            match chars.peek() {
+                None => return content[..i].trim().to_string(),
+                Some(&(_, next)) if next.is_whitespace() => return content[..i].trim().to_string(),
+                _ => ()
+            }
+        }
+    }
+
+    content.trim().to_string()
+}
+
+fn tags_of(content: &str) -> Vec<String> {
+    let mut tags: Vec<String> = vec![];
+
+    for word in content.split_whitespace() {
+        if !word.starts_with('@') {
+            continue;
+        }
+
+        let tag: String = word[1..].chars()
+            .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '-')
+            .collect();
+
+        if !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+
+    tags
+}
+
+// Stable across runs and versions of rjrn: two FNV-1a hashes of the seed,
+// marked as a custom (version 8) UUID
+fn derive_id(seed: &str) -> Uuid {
+    let mut bytes = [0u8; 16];
+
+    for (half, basis) in [0xcbf29ce484222325u64, 0x84222325cbf29ce4u64].iter().enumerate() {
+        let mut hash = *basis;
+        for byte in seed.bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+
+        for i in 0..8 {
+            bytes[half * 8 + i] = (hash >> (i * 8)) as u8;
+        }
+    }
+
+    bytes[6] = (bytes[6] & 0x0f) | 0x80;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    Uuid::from_bytes(bytes)
+}
+
+pub struct JrnlJournalFactory;
+
+impl JournalFactory for JrnlJournalFactory {
+    fn kind(&self) -> &'static str {
+        "JrnlJournal"
+    }
+
+    fn description(&self) -> &'static str {
+        "jrnl Journal (plain text, compatible with jrnl)"
+    }
+
+    fn bootstrap_cli(&self, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        Ok(Box::new(JrnlJournal::bootstrap_cli()?))
+    }
+
+    fn from_json(&self, journal: &Value, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        Ok(Box::new(JrnlJournal::from_json(journal)?))
+    }
+
+    fn from_options(&self, options: &JournalOptions, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        Ok(Box::new(JrnlJournal::new(&options.name, options.path()?)))
+    }
+}
+
+impl PartialEq for JrnlJournal {
+     fn eq(&self, other: &JrnlJournal) -> bool {
+         self.path == other.path
+     }
+ }
+
+#[cfg(test)]
+mod tests {
+    use entry::EntryBuilder;
+    use file_lock::FileLock;
+    use journal::Journal;
+    use std::fs;
+    use std::path::Path;
+    use std::time::Duration;
+    use test_support::{entry, TempDir};
+    use super::{parse, to_jrnl, JrnlJournal};
+
+    const JRNL: &str = "Written before the first entry\n\
+                        [2026-01-01 09:30] Fixed the build. It was the cache *\n\
+                        again @work @ci\n\
+                        \n\
+                        [2026-01-02 18:00] Evening run.\n\
+                        \n\
+                        [2026-01-02 18:00] Evening run. Slower @running\n";
+
+    fn journal(dir: &TempDir) -> JrnlJournal {
+        fs::create_dir_all(dir.join("")).unwrap();
+        let mut journal = JrnlJournal::new("notes", &dir.join("journal.txt").to_string_lossy());
+        journal.lock_timeout_ms = 100;
+        journal
+    }
+
+    #[test]
+    fn changes_wait_for_the_lock() {
+        let dir = TempDir::new("jrnl");
+        let journal = journal(&dir);
+        let note = entry("Milk and eggs.");
+
+        let lock = FileLock::acquire(Path::new(&journal.path), Duration::from_millis(100)).unwrap();
+        assert!(journal.upsert_entry(note.clone()).unwrap_err().contains("Timed out"));
+        assert!(journal.remove_entries(&vec![*note.id()]).is_err());
+
+        drop(lock);
+        journal.upsert_entry(note.clone()).unwrap();
+        assert_eq!(journal.entries().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn updates_keep_revisions_next_to_the_file() {
+        let dir = TempDir::new("jrnl");
+        let journal = journal(&dir);
+        let note = entry("Milk. And eggs");
+        journal.upsert_entry(note.clone()).unwrap();
+        journal.upsert_entry(EntryBuilder::from_entry(&note).raw_content("Milk. And bread".to_string())
+                             .finalize().unwrap()).unwrap();
+
+        let saved = &journal.entries().unwrap()[0];
+        assert_eq!(saved.id(), note.id());
+        assert_eq!(saved.revisions().len(), 1);
+        assert_eq!(saved.revisions()[0].content(), "Milk. And eggs");
+
+        journal.compact().unwrap();
+        assert!(journal.entries().unwrap()[0].revisions().is_empty());
+    }
+
+    #[test]
+    fn both_files_are_replaced_with_backups() {
+        let dir = TempDir::new("jrnl");
+        let journal = journal(&dir);
+        journal.upsert_entry(entry("Milk and eggs.")).unwrap();
+        journal.upsert_entry(entry("Bread.")).unwrap();
+
+        let files = journal.files();
+        for name in ["journal.txt", "journal.txt.bak", "journal.txt.rjrn", "journal.txt.rjrn.bak"].iter() {
+            assert!(files.contains(&dir.join(name)), "{} is missing", name);
+        }
+        assert_eq!(fs::read_to_string(dir.join("journal.txt.bak")).unwrap().matches('[').count(), 1);
+    }
+
+    #[test]
+    fn jrnl_text_is_parsed_with_stable_ids() {
+        let entries = parse(JRNL);
+        assert_eq!(entries.len(), 3);
+
+        assert_eq!(entries[0].title(), &Some("Fixed the build".to_string()));
+        assert_eq!(entries[0].content(), "Fixed the build. It was the cache\nagain @work @ci");
+        assert_eq!(entries[0].tags(), &vec!["work", "ci"]);
+        assert!(entries[0].starred());
+        assert!(!entries[1].starred());
+
+        // Same date and title, told apart by their order
+        assert_eq!(entries[1].title(), entries[2].title());
+        assert!(entries[1].id() != entries[2].id());
+        let ids: Vec<_> = parse(JRNL).iter().map(|e| *e.id()).collect();
+        assert_eq!(ids, entries.iter().map(|e| *e.id()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn written_entries_read_back_the_same() {
+        let mut builder = EntryBuilder::new();
+        builder.content("Release. Shipped v2\nto everyone".to_string()).tag("work").starred(true);
+        let entry = builder.finalize().unwrap();
+
+        let text = to_jrnl(&entry);
+        assert!(text.ends_with("] Release. Shipped v2 *\nto everyone @work\n"), "{}", text);
+
+        let read = &parse(&text)[0];
+        assert_eq!(read.title(), entry.title());
+        assert_eq!(read.tags(), entry.tags());
+        assert!(read.starred());
+    }
+
+    #[test]
+    fn files_written_by_jrnl_can_be_changed() {
+        let dir = TempDir::new("jrnl");
+        let journal = journal(&dir);
+        fs::write(dir.join("journal.txt"), JRNL).unwrap();
+
+        let entries = journal.entries().unwrap();
+        assert_eq!(entries.len(), 3);
+        journal.upsert_entry(EntryBuilder::from_entry(&entries[1]).raw_content("Evening run. 5k".to_string())
+                             .finalize().unwrap()).unwrap();
+        journal.remove_entries(&vec![*entries[2].id()]).unwrap();
+
+        let after = journal.entries().unwrap();
+        assert_eq!(after.len(), 2);
+        assert_eq!(after[0].id(), entries[0].id());
+        assert_eq!(after[1].id(), entries[1].id());
+        assert_eq!(after[1].content(), "Evening run. 5k");
+    }
+}
@@ -0,0 +1,355 @@
+//! JSON Lines Journal keeps an append-only log of records, one per line.
+//! Adding or updating an entry appends its new revision, removing appends
+//! a tombstone, so no write has to read the rest of the file.
+//! Earlier lines of an entry are its revisions, `compact` rewrites the file
+//! with only the current entries and drops them.
+//!
+//! Appends and `compact` are made under a lock on `<path>.lock`, `compact`
+//! replaces the file atomically and keeps the old one as a backup.
+//!
+//! Usage for bootstraping and saving:
+//!
+//!```
+//!    use rjrn::journal::Journal;
+//!    use rjrn::jsonl_journal::JsonlJournal;
+//!    let journal = JsonlJournal::new("name", "path.jsonl");
+//!    assert_eq!(journal, JsonlJournal::from_json(&journal.to_json()).unwrap());
+//!```
+
+use serde_json::{self, Map, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use uuid::Uuid;
+
+use journal::{config_string, file_with_siblings, Journal, JournalFactory, JournalOptions, JournalRegistry};
+use entry::Entry;
+use prompt;
+use atomic_write;
+use file_lock::{self, FileLock};
+
+const UPSERT: &str = "upsert";
+const DELETE: &str = "delete";
+
+// Single line of the journal. Deletions carry only the id of the entry.
+#[derive(Deserialize, Serialize)]
+#[derive(Debug)]
+struct Record {
+    op: String,
+    id: Uuid,
+    entry: Option<Entry>,
+}
+
+#[derive(Debug)]
+pub struct JsonlJournal {
+    name: String,
+    path: String,
+    default: bool,
+    // How long to wait for other rjrn processes writing to the same file
+    lock_timeout_ms: u64,
+}
+
+impl Journal for JsonlJournal {
+    fn to_json(&self) -> Value {
+        let mut d = Map::new();
+        d.insert("name".to_string(), Value::String(self.name.clone()));
+        d.insert("path".to_string(), Value::String(self.path.clone()));
+        d.insert("type".to_string(), Value::String("JsonlJournal".to_string()));
+        d.insert("default".to_string(), Value::Bool(self.default));
+        d.insert("lock_timeout_ms".to_string(), Value::from(self.lock_timeout_ms));
+        Value::Object(d)
+    }
+
+    fn set_default(&mut self) {
+        self.default = true
+    }
+
+    fn is_default(&self) -> bool {
+        self.default
+    }
+
+    fn name(&self) -> &String {
+        &self.name
+    }
+
+    fn entries(&self) -> Result<Vec<Box<Entry>>, String> {
+        let mut positions: HashMap<Uuid, usize> = HashMap::new();
+        let mut entries: Vec<Option<Box<Entry>>> = vec![];
+
+        for record in self.records()?.into_iter() {
+            match (record.op == DELETE, record.entry) {
+                (false, Some(mut entry)) => match positions.get(&record.id) {
+                    // Earlier lines of the entry are its revisions
+                    Some(&i) => {
+                        if let Some(ref previous) = entries[i] {
+                            entry.keep_history_of(previous);
+                        }
+                        entries[i] = Some(Box::new(entry));
+                    },
+                    None => {
+                        positions.insert(record.id, entries.len());
+                        entries.push(Some(Box::new(entry)));
+                    }
+                },
+                _ => if let Some(i) = positions.remove(&record.id) {
+                    entries[i] = None;
+                }
+            }
+        }
+
+        Ok(entries.into_iter().flatten().collect())
+    }
+
+    fn upsert_entry(&self, entry: Entry) -> Result<Uuid, String> {
+        debug!("Append entry with title:: {:?} to - {:?}", entry.title(), self.path);
+        let id = *entry.id();
+        self.append(&vec![Record { op: UPSERT.to_string(), id, entry: Some(entry) }])?;
+        Ok(id)
+    }
+
+    fn remove_entries(&self, ids: &Vec<Uuid>) -> Result<(), String> {
+        self.append(&ids.iter()
+                    .map(|id| Record { op: DELETE.to_string(), id: *id, entry: None })
+                    .collect())
+    }
+
+    fn files(&self) -> Vec<PathBuf> {
+        file_with_siblings(&self.path, &[])
+    }
+
+    fn compact(&self) -> Result<(), String> {
+        let _lock = self.lock()?;
+        let mut lines = String::new();
+        for entry in self.entries()?.into_iter() {
+            let mut entry = *entry;
+            entry.forget_revisions();
+            let record = Record { op: UPSERT.to_string(), id: *entry.id(), entry: Some(entry) };
+            lines.push_str(&serde_json::to_string(&record).unwrap());
+            lines.push('\n');
+        }
+
+        atomic_write::write(Path::new(&self.path), lines.as_bytes())
+            .map_err(|why| format!("Couldn't compact journal because: {}",
+                                   why))
+    }
+}
+
+impl JsonlJournal {
+    pub fn new(name: &str, path: &str) -> JsonlJournal {
+        JsonlJournal {
+            name: name.to_string(),
+            path: path.to_string(),
+            default: false,
+            lock_timeout_ms: file_lock::DEFAULT_TIMEOUT_MS,
+        }
+    }
+
+    pub fn from_json(j: &Value) -> Result<JsonlJournal, String> {
+        Ok(JsonlJournal {
+            name: config_string(j, "name")?,
+            path: config_string(j, "path")?,
+            default: j.get("default").and_then(|d| d.as_bool()).unwrap_or(false),
+            lock_timeout_ms: j.get("lock_timeout_ms").and_then(|t| t.as_u64())
+                .unwrap_or(file_lock::DEFAULT_TIMEOUT_MS),
+        })
+    }
+
+    // Used by the CLI when the user want to create a new journal of that type
+    pub fn bootstrap_cli() -> Result<JsonlJournal, String> {
+        let name = prompt::journal_name();
+        let path = prompt::journal_path(&format!("rjrn-{}.jsonl", name))?;
+
+        Ok(JsonlJournal::new(&name, &path))
+    }
+
+    fn records(&self) -> Result<Vec<Record>, String> {
+        let file = match OpenOptions::new().read(true).open(&self.path) {
+            Ok(file) => file,
+            Err(_) => return Ok(vec![])
+        };
+
+        let mut records = vec![];
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|why| format!("Couldn't read entries {}",
+                                                  why))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            // A line cut by an interrupted append shouldn't hide the rest of the journal
+            match serde_json::from_str::<Record>(&line) {
+                Ok(record) => records.push(record),
+                Err(why) => warn!("Skipping line {} of {}: {}", i + 1, self.path, why)
+            }
+        }
+
+        Ok(records)
+    }
+
+    // Held while appending, so lines of two processes or a compact in between
+    // don't get mixed up
+    fn lock(&self) -> Result<FileLock, String> {
+        FileLock::acquire(Path::new(&self.path), Duration::from_millis(self.lock_timeout_ms))
+    }
+
+    fn append(&self, records: &Vec<Record>) -> Result<(), String> {
+        let _lock = self.lock()?;
+        // The line cut by an interrupted append is skipped, the new ones aren't
+        let mut lines = if self.ends_in_a_cut_line()? { "\n".to_string() } else { String::new() };
+        for record in records.iter() {
+            lines.push_str(&serde_json::to_string(record).unwrap());
+            lines.push('\n');
+        }
+
+        let mut file = OpenOptions::new().append(true).create(true)
+                       .open(&self.path)
+                       .map_err(|why| format!("Couldn't open journal file because: {}",
+                                              why))?;
+
+        file.write_all(lines.as_bytes())
+            .map_err(|why| format!("Couldn't save the entry because: {}",
+                                   why))
+    }
+
+    fn ends_in_a_cut_line(&self) -> Result<bool, String> {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(_) => return Ok(false)
+        };
+
+        let mut last = [b'\n'];
+        if file.seek(SeekFrom::End(0)).map(|len| len > 0).unwrap_or(false) {
+            file.seek(SeekFrom::End(-1))
+            .and_then(|_| file.read_exact(&mut last))
+            .map_err(|why| format!("Couldn't read entries {}",
+                                   why))?;
+        }
+        Ok(last[0] != b'\n')
+    }
+}
+
+pub struct JsonlJournalFactory;
+
+impl JournalFactory for JsonlJournalFactory {
+    fn kind(&self) -> &'static str {
+        "JsonlJournal"
+    }
+
+    fn description(&self) -> &'static str {
+        "JSON Lines Journal (append-only)"
+    }
+
+    fn bootstrap_cli(&self, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        Ok(Box::new(JsonlJournal::bootstrap_cli()?))
+    }
+
+    fn from_json(&self, journal: &Value, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        Ok(Box::new(JsonlJournal::from_json(journal)?))
+    }
+
+    fn from_options(&self, options: &JournalOptions, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        Ok(Box::new(JsonlJournal::new(&options.name, options.path()?)))
+    }
+}
+
+impl PartialEq for JsonlJournal {
+     fn eq(&self, other: &JsonlJournal) -> bool {
+         self.path == other.path
+     }
+ }
+
+#[cfg(test)]
+mod tests {
+    use entry::{Entry, EntryBuilder};
+    use file_lock::FileLock;
+    use journal::Journal;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::path::Path;
+    use std::time::Duration;
+    use test_support::{entry, TempDir};
+    use super::JsonlJournal;
+
+    fn journal(dir: &TempDir) -> JsonlJournal {
+        fs::create_dir_all(dir.join("")).unwrap();
+        let mut journal = JsonlJournal::new("notes", &dir.join("journal.jsonl").to_string_lossy());
+        journal.lock_timeout_ms = 100;
+        journal
+    }
+
+    fn edited(entry: &Entry, content: &str) -> Entry {
+        EntryBuilder::from_entry(entry).raw_content(content.to_string()).finalize().unwrap()
+    }
+
+    fn lines(journal: &JsonlJournal) -> usize {
+        fs::read_to_string(&journal.path).unwrap().lines().count()
+    }
+
+    #[test]
+    fn changes_are_appended_as_revisions_and_tombstones() {
+        let dir = TempDir::new("jsonl");
+        let journal = journal(&dir);
+        let milk = entry("Milk");
+        let bread = entry("Bread");
+
+        journal.upsert_entry(milk.clone()).unwrap();
+        journal.upsert_entry(bread.clone()).unwrap();
+        journal.upsert_entry(edited(&milk, "Milk and eggs")).unwrap();
+        journal.remove_entries(&vec![*bread.id()]).unwrap();
+        assert_eq!(lines(&journal), 4);
+
+        let entries = journal.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].content(), "Milk and eggs");
+        assert_eq!(entries[0].revisions()[0].content(), "Milk");
+    }
+
+    #[test]
+    fn appends_after_a_cut_line_start_on_a_new_line() {
+        let dir = TempDir::new("jsonl");
+        let journal = journal(&dir);
+        journal.upsert_entry(entry("Milk")).unwrap();
+        OpenOptions::new().append(true).open(&journal.path).unwrap()
+            .write_all(b"{\"op\":\"upsert\",\"id\":").unwrap();
+
+        journal.upsert_entry(entry("Bread")).unwrap();
+        let entries = journal.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].content(), "Bread");
+    }
+
+    #[test]
+    fn compact_keeps_only_current_entries() {
+        let dir = TempDir::new("jsonl");
+        let journal = journal(&dir);
+        let milk = entry("Milk");
+        let bread = entry("Bread");
+        journal.upsert_entry(milk.clone()).unwrap();
+        journal.upsert_entry(edited(&milk, "Milk and eggs")).unwrap();
+        journal.upsert_entry(bread.clone()).unwrap();
+        journal.remove_entries(&vec![*bread.id()]).unwrap();
+
+        journal.compact().unwrap();
+        assert_eq!(lines(&journal), 1);
+        let entries = journal.entries().unwrap();
+        assert_eq!(entries[0].content(), "Milk and eggs");
+        assert!(entries[0].revisions().is_empty());
+        assert!(journal.files().contains(&dir.join("journal.jsonl.bak")));
+    }
+
+    #[test]
+    fn changes_wait_for_the_lock() {
+        let dir = TempDir::new("jsonl");
+        let journal = journal(&dir);
+
+        let lock = FileLock::acquire(Path::new(&journal.path), Duration::from_millis(100)).unwrap();
+        assert!(journal.upsert_entry(entry("Milk")).unwrap_err().contains("Timed out"));
+        assert!(journal.compact().is_err());
+
+        drop(lock);
+        journal.upsert_entry(entry("Milk")).unwrap();
+        journal.compact().unwrap();
+    }
+}
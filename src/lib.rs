@@ -1,6 +1,21 @@
+// `&Box<dyn Journal>` and `&Vec<_>` are part of the public API
+#![allow(clippy::borrowed_box, clippy::ptr_arg, clippy::vec_box, clippy::wrong_self_convention)]
+
 extern crate uuid;
 extern crate chrono;
-extern crate rustc_serialize;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate base64;
+extern crate rusqlite;
+extern crate ureq;
+extern crate argon2;
+extern crate chacha20poly1305;
+extern crate getrandom;
+extern crate rpassword;
+extern crate fs2;
+extern crate regex;
 #[macro_use]
 extern crate log;
 
@@ -8,4 +23,24 @@ pub mod config;
 pub mod entry;
 pub mod journal;
 pub mod file_journal;
+pub mod encrypted_file_journal;
+pub mod jsonl_journal;
+pub mod sqlite_journal;
+pub mod markdown_dir_journal;
+pub mod jrnl_journal;
+pub mod trello_journal;
+pub mod git_journal;
+pub mod memory_journal;
+pub mod front_matter;
+pub mod prompt;
+pub mod atomic_write;
+pub mod file_lock;
+pub mod search;
+pub mod query;
+pub mod trash;
+pub mod operation_log;
+pub mod store;
 pub mod simple_logger;
+
+#[cfg(test)]
+mod test_support;
@@ -1,19 +1,35 @@
+// `&Box<dyn Journal>` and `&Vec<_>` are part of the public API
+#![allow(clippy::borrowed_box, clippy::ptr_arg, clippy::vec_box, clippy::wrong_self_convention)]
+
 extern crate rjrn;
-extern crate rustc_serialize;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate chrono;
+extern crate uuid;
+#[macro_use]
+extern crate log;
 
 use rjrn::{simple_logger};
+use std::io::{self, Write};
+use std::process;
 
 mod cli;
 mod cli_args;
+mod output;
+mod editor;
 
 fn main() {
     let args: cli_args::Args = cli_args::get();
     simple_logger::init(args.flag_verbose)
-        .ok()
         .expect("Something went wrong with the logger");
 
     match cli::process_args(&args) {
         Ok(_) => (),
-        Err(why) => println!("There was an error: {}", why)
+        Err(why) => {
+            let _ = writeln!(io::stderr(), "There was an error: {}", why);
+            process::exit(1);
+        }
     }
 }
@@ -0,0 +1,486 @@
+//! Markdown Directory Journal keeps every entry in its own `.md` file, with
+//! the id, title, dates, star and tags in YAML front matter and the content
+//! as the body. New entries are saved as `<id>.md`, but files can be renamed,
+//! the id from the front matter is what identifies the entry.
+//! Entry files are replaced atomically, without backups, under a lock on
+//! `.rjrn.lock` in the directory. Earlier versions of the entries are kept
+//! in `.rjrn-revisions.json` next to them.
+//!
+//! Usage for bootstraping and saving:
+//!
+//!```
+//!    use rjrn::journal::Journal;
+//!    use rjrn::markdown_dir_journal::MarkdownDirJournal;
+//!    let journal = MarkdownDirJournal::new("name", "notes");
+//!    assert_eq!(journal, MarkdownDirJournal::from_json(&journal.to_json()).unwrap());
+//!```
+
+use serde_json::{self, Map};
+use chrono::*;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use uuid::Uuid;
+
+use journal::{config_string, Journal, JournalFactory, JournalOptions, JournalRegistry};
+use entry::{Entry, EntryBuilder, Revision};
+use front_matter::{self, Value};
+use prompt;
+use atomic_write;
+use file_lock::{self, FileLock};
+
+const EXTENSION: &str = "md";
+// Locked as `.rjrn.lock`
+const LOCK_NAME: &str = ".rjrn";
+// Revisions of every entry by its id, oldest first
+const REVISIONS_NAME: &str = ".rjrn-revisions.json";
+
+#[derive(Debug)]
+pub struct MarkdownDirJournal {
+    name: String,
+    path: String,
+    default: bool,
+    // How long to wait for other rjrn processes writing to the directory
+    lock_timeout_ms: u64,
+}
+
+impl Journal for MarkdownDirJournal {
+    fn to_json(&self) -> serde_json::Value {
+        let mut d = Map::new();
+        d.insert("name".to_string(), serde_json::Value::String(self.name.clone()));
+        d.insert("path".to_string(), serde_json::Value::String(self.path.clone()));
+        d.insert("type".to_string(), serde_json::Value::String("MarkdownDirJournal".to_string()));
+        d.insert("default".to_string(), serde_json::Value::Bool(self.default));
+        d.insert("lock_timeout_ms".to_string(), serde_json::Value::from(self.lock_timeout_ms));
+        serde_json::Value::Object(d)
+    }
+
+    fn set_default(&mut self) {
+        self.default = true
+    }
+
+    fn is_default(&self) -> bool {
+        self.default
+    }
+
+    fn name(&self) -> &String {
+        &self.name
+    }
+
+    // Sorted by created_at, the order of files in a directory means nothing
+    fn entries(&self) -> Result<Vec<Box<Entry>>, String> {
+        let mut revisions = self.revisions()?;
+        let mut entries: Vec<Box<Entry>> = self.entry_files()?
+            .into_iter()
+            .map(|(_, mut entry)| {
+                entry.set_revisions(revisions.remove(&entry.id().hyphenated().to_string()).unwrap_or(vec![]));
+                Box::new(entry)
+            })
+            .collect();
+
+        entries.sort_by_key(|a| a.created_at());
+        Ok(entries)
+    }
+
+    fn upsert_entry(&self, entry: Entry) -> Result<Uuid, String> {
+        debug!("Add entry with title:: {:?} to - {:?}", entry.title(), self.path);
+        fs::create_dir_all(&self.path)
+        .map_err(|why| format!("Couldn't create journal directory because: {}",
+                               why))?;
+
+        let _lock = self.lock()?;
+        let mut revisions = self.revisions()?;
+        let key = entry.id().hyphenated().to_string();
+        let mut entry = entry;
+
+        let path = match self.find_file(entry.id())? {
+            Some((path, mut previous)) => {
+                previous.set_revisions(revisions.remove(&key).unwrap_or(vec![]));
+                entry.keep_history_of(&previous);
+                path
+            },
+            None => Path::new(&self.path)
+                .join(format!("{}.{}", key, EXTENSION))
+        };
+
+        atomic_write::replace(&path, to_markdown(&entry).as_bytes())
+        .map_err(|why| format!("Couldn't save the entry because: {}",
+                               why))?;
+
+        if !entry.revisions().is_empty() {
+            revisions.insert(key, entry.revisions().to_vec());
+            self.save_revisions(&revisions)?;
+        }
+        Ok(*entry.id())
+    }
+
+    fn remove_entries(&self, ids: &Vec<Uuid>) -> Result<(), String> {
+        if !Path::new(&self.path).is_dir() {
+            return Ok(());
+        }
+
+        let _lock = self.lock()?;
+        for (path, entry) in self.entry_files()?.into_iter() {
+            if ids.contains(entry.id()) {
+                debug!("Removing entry file: {:?}", path);
+                fs::remove_file(&path)
+                .map_err(|why| format!("Couldn't remove entry file because: {}",
+                                       why))?;
+            }
+        }
+
+        let mut revisions = self.revisions()?;
+        let before = revisions.len();
+        revisions.retain(|id, _| !ids.iter().any(|i| i.hyphenated().to_string() == *id));
+        if revisions.len() != before {
+            self.save_revisions(&revisions)?;
+        }
+        Ok(())
+    }
+
+    fn files(&self) -> Vec<PathBuf> {
+        // Only entry files and rjrn's own, other files in the directory aren't the journal's
+        let mut files: Vec<PathBuf> = self.entry_files()
+            .map(|files| files.into_iter().map(|(path, _)| path).collect())
+            .unwrap_or(vec![]);
+
+        for file in [self.revisions_path(), file_lock::lock_path(&self.lock_base())].iter() {
+            if file.exists() {
+                files.push(file.clone());
+            }
+        }
+        files
+    }
+
+    fn compact(&self) -> Result<(), String> {
+        if !self.revisions_path().exists() {
+            return Ok(());
+        }
+
+        let _lock = self.lock()?;
+        fs::remove_file(self.revisions_path())
+        .map_err(|why| format!("Couldn't remove the revisions because: {}",
+                               why))
+    }
+}
+
+impl MarkdownDirJournal {
+    pub fn new(name: &str, path: &str) -> MarkdownDirJournal {
+        MarkdownDirJournal {
+            name: name.to_string(),
+            path: path.to_string(),
+            default: false,
+            lock_timeout_ms: file_lock::DEFAULT_TIMEOUT_MS,
+        }
+    }
+
+    pub fn from_json(j: &serde_json::Value) -> Result<MarkdownDirJournal, String> {
+        Ok(MarkdownDirJournal {
+            name: config_string(j, "name")?,
+            path: config_string(j, "path")?,
+            default: j.get("default").and_then(|d| d.as_bool()).unwrap_or(false),
+            lock_timeout_ms: j.get("lock_timeout_ms").and_then(|t| t.as_u64())
+                .unwrap_or(file_lock::DEFAULT_TIMEOUT_MS),
+        })
+    }
+
+    // Used by the CLI when the user want to create a new journal of that type
+    pub fn bootstrap_cli() -> Result<MarkdownDirJournal, String> {
+        let name = prompt::journal_name();
+        let path = prompt::journal_path(&format!("rjrn-{}", name))?;
+
+        MarkdownDirJournal::create(&name, &path)
+    }
+
+    // New journal with its directory created
+    pub fn create(name: &str, path: &str) -> Result<MarkdownDirJournal, String> {
+        fs::create_dir_all(path)
+        .map_err(|why| format!("Couldn't create journal directory because: {}",
+                               why))?;
+
+        Ok(MarkdownDirJournal::new(name, path))
+    }
+
+    // Held while entry files are looked up and written, so two processes
+    // never save the same entry to different files
+    fn lock(&self) -> Result<FileLock, String> {
+        FileLock::acquire(&self.lock_base(), Duration::from_millis(self.lock_timeout_ms))
+    }
+
+    fn lock_base(&self) -> PathBuf {
+        Path::new(&self.path).join(LOCK_NAME)
+    }
+
+    // Every readable entry with the file it comes from
+    fn entry_files(&self) -> Result<Vec<(PathBuf, Entry)>, String> {
+        let dir = match fs::read_dir(&self.path) {
+            Ok(dir) => dir,
+            Err(_) => return Ok(vec![])
+        };
+
+        let mut entries = vec![];
+        for file in dir {
+            let path = file.map_err(|why| format!("Couldn't read entries {}",
+                                                 why))?.path();
+
+            if path.extension().and_then(|e| e.to_str()) != Some(EXTENSION) {
+                continue;
+            }
+
+            match read_entry(&path) {
+                Ok(entry) => entries.push((path, entry)),
+                Err(why) => warn!("Skipping {:?}: {}", path, why)
+            }
+        }
+
+        Ok(entries)
+    }
+
+    // The file of the entry with the entry as it's saved now
+    fn find_file(&self, id: &Uuid) -> Result<Option<(PathBuf, Entry)>, String> {
+        let path = Path::new(&self.path)
+            .join(format!("{}.{}", id.hyphenated(), EXTENSION));
+
+        if let Ok(entry) = read_entry(&path) {
+            if entry.id() == id {
+                return Ok(Some((path, entry)));
+            }
+        }
+
+        Ok(self.entry_files()?
+           .into_iter()
+           .find(|(_, entry)| entry.id() == id))
+    }
+
+    fn revisions_path(&self) -> PathBuf {
+        Path::new(&self.path).join(REVISIONS_NAME)
+    }
+
+    fn revisions(&self) -> Result<BTreeMap<String, Vec<Revision>>, String> {
+        let mut text = String::new();
+        if File::open(self.revisions_path()).and_then(|mut file| file.read_to_string(&mut text)).is_err() ||
+            text.trim().is_empty() {
+            return Ok(BTreeMap::new());
+        }
+
+        serde_json::from_str(&text)
+            .map_err(|why| format!("Couldn't read {} because: {}", self.revisions_path().display(), why))
+    }
+
+    fn save_revisions(&self, revisions: &BTreeMap<String, Vec<Revision>>) -> Result<(), String> {
+        atomic_write::replace(&self.revisions_path(), serde_json::to_string(revisions).unwrap().as_bytes())
+            .map_err(|why| format!("Couldn't save the revisions because: {}", why))
+    }
+}
+
+fn to_markdown(entry: &Entry) -> String {
+    let title = match *entry.title() {
+        Some(ref title) => Value::Scalar(title.clone()),
+        None => Value::Null
+    };
+
+    front_matter::render(&vec![
+        ("id".to_string(), Value::Scalar(entry.id().hyphenated().to_string())),
+        ("title".to_string(), title),
+        ("created_at".to_string(), Value::Scalar(entry.created_at().to_rfc3339())),
+        ("updated_at".to_string(), Value::Scalar(entry.updated_at().to_rfc3339())),
+        ("starred".to_string(), Value::Scalar(entry.starred().to_string())),
+        ("tags".to_string(), Value::List(entry.tags().clone())),
+    ], entry.content())
+}
+
+fn read_entry(path: &Path) -> Result<Entry, String> {
+    let mut text = String::new();
+    File::open(path)
+    .and_then(|mut file| file.read_to_string(&mut text))
+    .map_err(|why| why.to_string())?;
+
+    let (fields, body) = front_matter::parse(&text)?;
+    let id = match fields.get("id").and_then(|id| id.as_str()) {
+        Some(id) => Uuid::parse_str(id).map_err(|_| format!("Invalid id: {}", id))?,
+        None => return Err("Missing id in the front matter".to_string())
+    };
+
+    let mut builder = EntryBuilder::new();
+    builder.id(id)
+        .raw_content(body)
+        .starred(fields.get("starred").and_then(|s| s.as_bool()).unwrap_or(false))
+        .tags(fields.get("tags").map(|t| t.as_list()).unwrap_or(vec![]));
+
+    if let Some(title) = fields.get("title").and_then(|t| t.as_str()) {
+        builder.title(title.to_string());
+    }
+    if let Some(created_at) = date_field(&fields, "created_at")? {
+        builder.created_at(created_at);
+    }
+    match date_field(&fields, "updated_at")? {
+        Some(updated_at) => { builder.updated_at(updated_at); },
+        None => if let Some(created_at) = date_field(&fields, "created_at")? {
+            builder.updated_at(created_at);
+        }
+    }
+
+    builder.finalize()
+}
+
+fn date_field(fields: &BTreeMap<String, Value>, key: &str) -> Result<Option<DateTime<Utc>>, String> {
+    match fields.get(key).and_then(|d| d.as_str()) {
+        Some(date) => DateTime::<Utc>::from_str(date)
+            .map(Some)
+            .map_err(|_| format!("Invalid {}: {}", key, date)),
+        None => Ok(None)
+    }
+}
+
+pub struct MarkdownDirJournalFactory;
+
+impl JournalFactory for MarkdownDirJournalFactory {
+    fn kind(&self) -> &'static str {
+        "MarkdownDirJournal"
+    }
+
+    fn description(&self) -> &'static str {
+        "Markdown Directory Journal (one file per entry)"
+    }
+
+    fn bootstrap_cli(&self, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        Ok(Box::new(MarkdownDirJournal::bootstrap_cli()?))
+    }
+
+    fn from_json(&self, journal: &serde_json::Value, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        Ok(Box::new(MarkdownDirJournal::from_json(journal)?))
+    }
+
+    fn from_options(&self, options: &JournalOptions, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        Ok(Box::new(MarkdownDirJournal::create(&options.name, options.path()?)?))
+    }
+}
+
+impl PartialEq for MarkdownDirJournal {
+     fn eq(&self, other: &MarkdownDirJournal) -> bool {
+         self.path == other.path
+     }
+ }
+
+#[cfg(test)]
+mod tests {
+    use entry::EntryBuilder;
+    use file_lock::FileLock;
+    use journal::Journal;
+    use std::fs;
+    use std::time::Duration;
+    use test_support::{entry, TempDir};
+    use super::{read_entry, to_markdown, MarkdownDirJournal};
+
+    fn journal(dir: &TempDir) -> MarkdownDirJournal {
+        let mut journal = MarkdownDirJournal::new("notes", &dir.join("notes").to_string_lossy());
+        journal.lock_timeout_ms = 100;
+        journal
+    }
+
+    #[test]
+    fn changes_wait_for_the_lock() {
+        let dir = TempDir::new("markdown-dir");
+        let journal = journal(&dir);
+        let note = entry("Milk and eggs");
+        journal.upsert_entry(entry("Bread")).unwrap();
+
+        let lock = FileLock::acquire(&journal.lock_base(), Duration::from_millis(100)).unwrap();
+        assert!(journal.upsert_entry(note.clone()).unwrap_err().contains("Timed out"));
+        assert!(journal.remove_entries(&vec![*note.id()]).is_err());
+
+        drop(lock);
+        journal.upsert_entry(note.clone()).unwrap();
+        assert_eq!(journal.entries().unwrap().len(), 2);
+        assert!(journal.files().contains(&dir.join("notes/.rjrn.lock")));
+    }
+
+    #[test]
+    fn updates_keep_revisions_next_to_the_entries() {
+        let dir = TempDir::new("markdown-dir");
+        let journal = journal(&dir);
+        let milk = entry("Milk");
+        let bread = entry("Bread");
+        journal.upsert_entry(milk.clone()).unwrap();
+        journal.upsert_entry(bread.clone()).unwrap();
+        for content in ["Milk and eggs", "Milk, eggs and flour"].iter() {
+            journal.upsert_entry(EntryBuilder::from_entry(&milk).raw_content(content.to_string())
+                                 .finalize().unwrap()).unwrap();
+        }
+        journal.upsert_entry(EntryBuilder::from_entry(&bread).raw_content("Rye bread".to_string())
+                             .finalize().unwrap()).unwrap();
+
+        let entries = journal.entries().unwrap();
+        let revisions: Vec<&String> = entries[0].revisions().iter().map(|r| r.content()).collect();
+        assert_eq!(revisions, ["Milk", "Milk and eggs"]);
+        assert!(journal.files().contains(&dir.join("notes/.rjrn-revisions.json")));
+
+        journal.remove_entries(&vec![*milk.id()]).unwrap();
+        journal.upsert_entry(milk.clone()).unwrap();
+        let entries = journal.entries().unwrap();
+        assert!(entries.iter().find(|e| e.id() == milk.id()).unwrap().revisions().is_empty());
+        assert_eq!(entries.iter().find(|e| e.id() == bread.id()).unwrap().revisions().len(), 1);
+
+        journal.compact().unwrap();
+        assert!(journal.entries().unwrap().iter().all(|e| e.revisions().is_empty()));
+    }
+
+    #[test]
+    fn entry_files_are_replaced_without_backups() {
+        let dir = TempDir::new("markdown-dir");
+        let journal = journal(&dir);
+        assert!(journal.remove_entries(&vec![]).is_ok());
+
+        let note = entry("Milk");
+        journal.upsert_entry(note.clone()).unwrap();
+        journal.upsert_entry(EntryBuilder::from_entry(&note).raw_content("Milk and eggs".to_string())
+                             .finalize().unwrap()).unwrap();
+
+        let names: Vec<String> = fs::read_dir(dir.join("notes")).unwrap()
+            .map(|f| f.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        // The entry and its revisions
+        assert_eq!(names.len(), 3, "{:?}", names);
+        assert_eq!(journal.entries().unwrap()[0].content(), "Milk and eggs");
+    }
+
+    #[test]
+    fn entries_round_trip_through_markdown_files() {
+        let dir = TempDir::new("markdown-dir");
+        fs::create_dir_all(dir.join("")).unwrap();
+        let mut builder = EntryBuilder::new();
+        builder.content("Release: v2. Shipped #work\n\n---\nNotes".to_string()).tag("ci").starred(true);
+        let entry = builder.finalize().unwrap();
+
+        fs::write(dir.join("entry.md"), to_markdown(&entry)).unwrap();
+        let read = read_entry(&dir.join("entry.md")).unwrap();
+        assert_eq!(read.id(), entry.id());
+        assert_eq!(read.title(), entry.title());
+        assert_eq!(read.content(), entry.content());
+        assert_eq!(read.tags(), entry.tags());
+        assert!(read.starred());
+        assert_eq!(read.created_at(), entry.created_at());
+        assert_eq!(read.updated_at(), entry.updated_at());
+    }
+
+    #[test]
+    fn hand_written_files_are_read() {
+        let dir = TempDir::new("markdown-dir");
+        fs::create_dir_all(dir.join("")).unwrap();
+        fs::write(dir.join("note.md"), "---\nid: 5f4ad8a2-3b1c-4d4e-9f00-8d2c0a7b6e11\ntitle: Groceries\n\
+                                        created_at: 2026-03-01T10:00:00+01:00\ntags:\n  - home\n---\nMilk\n").unwrap();
+        let entry = read_entry(&dir.join("note.md")).unwrap();
+        assert_eq!(entry.title(), &Some("Groceries".to_string()));
+        assert_eq!(entry.content(), "Milk");
+        assert_eq!(entry.tags(), &vec!["home"]);
+        assert!(!entry.starred());
+        assert_eq!(entry.created_at().to_rfc3339(), "2026-03-01T09:00:00+00:00");
+        assert_eq!(entry.updated_at(), entry.created_at());
+
+        fs::write(dir.join("no-id.md"), "---\ntitle: Groceries\n---\nMilk\n").unwrap();
+        assert!(read_entry(&dir.join("no-id.md")).unwrap_err().contains("Missing id"));
+    }
+}
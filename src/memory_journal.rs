@@ -0,0 +1,215 @@
+//! Memory Journal keeps its entries in memory only, for programs embedding
+//! rjrn and for tests that shouldn't touch the disk. Its entries are part of
+//! its JSON, so a config with memory journals can be read from a string.
+//!
+//! It isn't offered by `rjrn --add`, register `MemoryJournalFactory` to read
+//! it from a config.
+//!
+//! Usage for adding and removing entries:
+//!
+//!```
+//!    use rjrn::journal::Journal;
+//!    use rjrn::entry::EntryBuilder;
+//!    use rjrn::memory_journal::MemoryJournal;
+//!
+//!    let journal = MemoryJournal::new("name");
+//!    let mut builder = EntryBuilder::new();
+//!    journal.upsert_entry(builder.content("First note".to_string()).finalize().unwrap()).unwrap();
+//!    let mut builder = EntryBuilder::new();
+//!    journal.upsert_entry(builder.content("Second note".to_string()).finalize().unwrap()).unwrap();
+//!    assert_eq!(journal.entries().unwrap().len(), 2);
+//!
+//!    let second = journal.entries().unwrap()[1].id().clone();
+//!    journal.remove_entries(&vec![second]).unwrap();
+//!    let entries = journal.entries().unwrap();
+//!    assert_eq!(entries.len(), 1);
+//!    assert_eq!(*entries[0].content(), "First note".to_string());
+//!
+//!    assert_eq!(journal, MemoryJournal::from_json(&journal.to_json()).unwrap());
+//!```
+
+use serde_json::{self, Map, Value};
+use std::cell::RefCell;
+use uuid::Uuid;
+
+use journal::{Journal, JournalFactory, JournalOptions, JournalRegistry};
+use entry::Entry;
+use prompt;
+
+#[derive(Debug)]
+pub struct MemoryJournal {
+    name: String,
+    default: bool,
+    entries: RefCell<Vec<Entry>>,
+}
+
+impl Journal for MemoryJournal {
+    fn to_json(&self) -> Value {
+        let entries = serde_json::to_string(&*self.entries.borrow()).unwrap();
+
+        let mut d = Map::new();
+        d.insert("name".to_string(), Value::String(self.name.clone()));
+        d.insert("type".to_string(), Value::String("MemoryJournal".to_string()));
+        d.insert("default".to_string(), Value::Bool(self.default));
+        d.insert("entries".to_string(), serde_json::from_str::<Value>(&entries).unwrap());
+        Value::Object(d)
+    }
+
+    fn set_default(&mut self) {
+        self.default = true
+    }
+
+    fn is_default(&self) -> bool {
+        self.default
+    }
+
+    fn name(&self) -> &String {
+        &self.name
+    }
+
+    fn entries(&self) -> Result<Vec<Box<Entry>>, String> {
+        Ok(self.entries.borrow().iter().map(|e| Box::new(e.clone())).collect())
+    }
+
+    fn upsert_entry(&self, mut entry: Entry) -> Result<Uuid, String> {
+        debug!("Add entry with title:: {:?} to memory journal {}", entry.title(), self.name);
+        let mut entries = self.entries.borrow_mut();
+        let id = *entry.id();
+
+        match entries.iter().position(|e| e.id() == entry.id()) {
+            Some(i) => {
+                entry.keep_history_of(&entries[i]);
+                entries[i] = entry;
+            },
+            None => entries.push(entry)
+        }
+
+        Ok(id)
+    }
+
+    fn remove_entries(&self, ids: &Vec<Uuid>) -> Result<(), String> {
+        self.entries.borrow_mut().retain(|e| !ids.contains(e.id()));
+        Ok(())
+    }
+}
+
+impl MemoryJournal {
+    pub fn new(name: &str) -> MemoryJournal {
+        MemoryJournal {
+            name: name.to_string(),
+            default: false,
+            entries: RefCell::new(vec![]),
+        }
+    }
+
+    pub fn with_entries(name: &str, entries: Vec<Entry>) -> MemoryJournal {
+        let journal = MemoryJournal::new(name);
+        *journal.entries.borrow_mut() = entries;
+        journal
+    }
+
+    pub fn from_json(j: &Value) -> Result<MemoryJournal, String> {
+        let name = match j.get("name").and_then(|n| n.as_str()) {
+            Some(name) => name,
+            None => return Err("Memory journal config is missing its name".to_string())
+        };
+
+        let entries = match j.get("entries") {
+            Some(entries) => serde_json::from_str(&serde_json::to_string(entries).unwrap())
+                             .map_err(|why| format!("Couldn't read entries: {}", why))?,
+            None => vec![]
+        };
+
+        let mut journal = MemoryJournal::with_entries(name, entries);
+        journal.default = j.get("default").and_then(|d| d.as_bool()).unwrap_or(false);
+        Ok(journal)
+    }
+}
+
+pub struct MemoryJournalFactory;
+
+impl JournalFactory for MemoryJournalFactory {
+    fn kind(&self) -> &'static str {
+        "MemoryJournal"
+    }
+
+    fn description(&self) -> &'static str {
+        "Memory Journal (not saved anywhere)"
+    }
+
+    fn bootstrap_cli(&self, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        let name = prompt::journal_name();
+        Ok(Box::new(MemoryJournal::new(&name)))
+    }
+
+    fn from_json(&self, journal: &Value, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        Ok(Box::new(MemoryJournal::from_json(journal)?))
+    }
+
+    fn from_options(&self, options: &JournalOptions, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        Ok(Box::new(MemoryJournal::new(&options.name)))
+    }
+}
+
+// Memory journals have no path, they're the same when they hold the same entries
+impl PartialEq for MemoryJournal {
+     fn eq(&self, other: &MemoryJournal) -> bool {
+         self.to_json() == other.to_json()
+     }
+ }
+
+#[cfg(test)]
+mod tests {
+    use entry::EntryBuilder;
+    use journal::Journal;
+    use test_support::entry;
+    use super::MemoryJournal;
+
+    #[test]
+    fn upsert_returns_the_id_it_was_saved_with() {
+        let journal = MemoryJournal::new("notes");
+        let added = entry("First note");
+
+        assert_eq!(journal.upsert_entry(added.clone()).unwrap(), *added.id());
+        assert_eq!(journal.entries().unwrap()[0].id(), added.id());
+    }
+
+    #[test]
+    fn upsert_of_a_known_id_replaces_the_entry_and_keeps_its_revision() {
+        let journal = MemoryJournal::new("notes");
+        let added = entry("First note");
+        journal.upsert_entry(added.clone()).unwrap();
+
+        let edited = EntryBuilder::from_entry(&added).content("Edited note".to_string()).finalize().unwrap();
+        journal.upsert_entry(edited).unwrap();
+
+        let entries = journal.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(*entries[0].content(), "Edited note".to_string());
+        assert_eq!(entries[0].revisions().len(), 1);
+    }
+
+    #[test]
+    fn remove_entries_keeps_the_others() {
+        let journal = MemoryJournal::new("notes");
+        let (first, second) = (entry("First"), entry("Second"));
+        journal.upsert_entry(first.clone()).unwrap();
+        journal.upsert_entry(second.clone()).unwrap();
+
+        journal.remove_entries(&vec![*first.id()]).unwrap();
+
+        let entries = journal.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].id(), second.id());
+    }
+
+    #[test]
+    fn entries_survive_the_json_round_trip() {
+        let mut journal = MemoryJournal::with_entries("notes", vec![entry("Kept")]);
+        journal.set_default();
+
+        let read = MemoryJournal::from_json(&journal.to_json()).unwrap();
+        assert!(read.is_default());
+        assert_eq!(read, journal);
+    }
+}
@@ -0,0 +1,291 @@
+//! Log of the operations done on a journal, so they can be undone and redone.
+//! An operation keeps every entry it changed as it was before and after,
+//! `None` standing for an entry that didn't exist (added) or was removed.
+//! Undoing moves the operation to the undone list, redoing moves it back,
+//! and recording a new operation forgets what was undone.
+//! Every journal has its own log, next to the config in `~/.rjrn.undo/<journal>.json`,
+//! sealed by the journal like its trash, or in memory for configs without a file.
+//!
+//!```
+//!    use rjrn::entry::EntryBuilder;
+//!    use rjrn::journal::Journal;
+//!    use rjrn::memory_journal::MemoryJournal;
+//!    use rjrn::operation_log::{Change, Operation, OperationLog};
+//!    use std::fs;
+//!
+//!    let dir = std::env::temp_dir().join("rjrn-operation-log-doc");
+//!    let journal: Box<dyn Journal> = Box::new(MemoryJournal::new("default"));
+//!    let log = OperationLog::new(&dir.join("default.json"), 100, &journal);
+//!
+//!    let mut builder = EntryBuilder::new();
+//!    let entry = builder.content("Added".to_string()).finalize().unwrap();
+//!    log.record(Operation::new("Add entry", vec![Change::new("default", None, Some(entry))])).unwrap();
+//!
+//!    assert_eq!(log.last_done().unwrap().unwrap().description(), "Add entry");
+//!    let operation = log.last_done().unwrap().unwrap();
+//!    log.mark_undone(operation).unwrap();
+//!    assert!(log.last_done().unwrap().is_none());
+//!    let operation = log.last_undone().unwrap().unwrap();
+//!    assert_eq!(operation.description(), "Add entry");
+//!    log.mark_redone(operation).unwrap();
+//!    assert!(log.last_undone().unwrap().is_none());
+//!
+//!    fs::remove_dir_all(&dir).unwrap();
+//!```
+
+use chrono::*;
+use serde_json;
+use std::path::Path;
+use std::str::FromStr;
+
+use entry::Entry;
+use journal::Journal;
+use store::Store;
+
+// Older operations are forgotten and can't be undone anymore
+const MAX_OPERATIONS: usize = 100;
+
+#[derive(Deserialize, Serialize)]
+#[derive(Debug, Clone)]
+pub struct Change {
+    journal: String,
+    before: Option<Entry>,
+    after: Option<Entry>,
+}
+
+impl Change {
+    pub fn new(journal: &str, before: Option<Entry>, after: Option<Entry>) -> Change {
+        Change { journal: journal.to_string(), before, after }
+    }
+
+    // Name of the journal the entry is in
+    pub fn journal(&self) -> &str {
+        &self.journal
+    }
+
+    pub fn before(&self) -> &Option<Entry> {
+        &self.before
+    }
+
+    pub fn after(&self) -> &Option<Entry> {
+        &self.after
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[derive(Debug, Clone)]
+pub struct Operation {
+    description: String,
+    done_at: String,
+    changes: Vec<Change>,
+}
+
+impl Operation {
+    pub fn new(description: &str, changes: Vec<Change>) -> Operation {
+        Operation {
+            description: description.to_string(),
+            done_at: Utc::now().to_rfc3339(),
+            changes,
+        }
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    pub fn done_at(&self) -> DateTime<Utc> {
+        DateTime::<Utc>::from_str(&self.done_at).unwrap_or(Utc::now())
+    }
+
+    pub fn changes(&self) -> &Vec<Change> {
+        &self.changes
+    }
+
+    // Same operation with the entries as they were saved when undoing or redoing it
+    pub fn with_changes(&self, changes: Vec<Change>) -> Operation {
+        Operation {
+            description: self.description.clone(),
+            done_at: self.done_at.clone(),
+            changes,
+        }
+    }
+}
+
+// Both lists are oldest first
+#[derive(Deserialize, Serialize)]
+#[derive(Debug)]
+struct Log {
+    done: Vec<Operation>,
+    undone: Vec<Operation>,
+}
+
+#[derive(Debug)]
+pub struct OperationLog<'a> {
+    store: Store,
+    // Seals the log
+    journal: &'a Box<dyn Journal>,
+}
+
+impl<'a> OperationLog<'a> {
+    pub fn new(path: &Path, lock_timeout_ms: u64, journal: &'a Box<dyn Journal>) -> OperationLog<'a> {
+        OperationLog::with_store(Store::file(path, lock_timeout_ms), journal)
+    }
+
+    pub fn with_store(store: Store, journal: &'a Box<dyn Journal>) -> OperationLog<'a> {
+        OperationLog { store, journal }
+    }
+
+    // None when the log is kept in memory
+    pub fn path(&self) -> Option<&Path> {
+        self.store.path()
+    }
+
+    pub fn record(&self, operation: Operation) -> Result<(), String> {
+        debug!("Recording operation: {} in {}", operation.description(), self.store.describe());
+        self.update(|log| {
+            log.done.push(operation);
+            let extra = log.done.len().saturating_sub(MAX_OPERATIONS);
+            log.done.drain(..extra);
+            log.undone.clear();
+        })
+    }
+
+    // Operation `--undo` would revert
+    pub fn last_done(&self) -> Result<Option<Operation>, String> {
+        Ok(self.read()?.done.pop())
+    }
+
+    // Operation `--redo` would apply again
+    pub fn last_undone(&self) -> Result<Option<Operation>, String> {
+        Ok(self.read()?.undone.pop())
+    }
+
+    // Called once the last operation was reverted, with the operation as it
+    // should be redone
+    pub fn mark_undone(&self, operation: Operation) -> Result<(), String> {
+        self.update(|log| if log.done.pop().is_some() {
+            log.undone.push(operation);
+        })
+    }
+
+    // Called once the last undone operation was applied again
+    pub fn mark_redone(&self, operation: Operation) -> Result<(), String> {
+        self.update(|log| if log.undone.pop().is_some() {
+            log.done.push(operation);
+        })
+    }
+
+    fn read(&self) -> Result<Log, String> {
+        self.parse(&self.store.read()?)
+    }
+
+    fn parse(&self, text: &str) -> Result<Log, String> {
+        if text.trim().is_empty() {
+            return Ok(Log { done: vec![], undone: vec![] });
+        }
+
+        serde_json::from_str(&self.journal.unseal(text)?)
+            .map_err(|why| format!("Couldn't read the operation log {} because: {}",
+                                   self.store.describe(), why))
+    }
+
+    fn update<F: FnOnce(&mut Log)>(&self, change: F) -> Result<(), String> {
+        self.store.update(|text| {
+            let mut log = self.parse(text)?;
+            change(&mut log);
+            self.journal.seal(&serde_json::to_string(&log).unwrap())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use journal::Journal;
+    use memory_journal::MemoryJournal;
+    use test_support::{entry, TempDir};
+    use super::{Change, Operation, OperationLog, MAX_OPERATIONS};
+
+    fn added(description: &str) -> Operation {
+        Operation::new(description, vec![Change::new("notes", None, Some(entry(description)))])
+    }
+
+    #[test]
+    fn add_can_be_undone_and_redone_on_the_journal() {
+        let dir = TempDir::new("operation-log");
+        let journal: Box<dyn Journal> = Box::new(MemoryJournal::new("notes"));
+        let log = OperationLog::new(&dir.join("notes.json"), 100, &journal);
+
+        let note = entry("Added by mistake");
+        journal.upsert_entry(note.clone()).unwrap();
+        log.record(Operation::new("Add entry", vec![Change::new("notes", None, Some(note.clone()))])).unwrap();
+
+        let operation = log.last_done().unwrap().unwrap();
+        let change = &operation.changes()[0];
+        assert!(change.before().is_none());
+        journal.remove_entries(&vec![*change.after().as_ref().unwrap().id()]).unwrap();
+        log.mark_undone(operation.clone()).unwrap();
+        assert!(journal.entries().unwrap().is_empty());
+
+        let operation = log.last_undone().unwrap().unwrap();
+        journal.upsert_entry(operation.changes()[0].after().clone().unwrap()).unwrap();
+        log.mark_redone(operation).unwrap();
+        assert_eq!(journal.entries().unwrap()[0].id(), note.id());
+        assert_eq!(log.last_done().unwrap().unwrap().description(), "Add entry");
+    }
+
+    #[test]
+    fn recording_forgets_what_was_undone() {
+        let dir = TempDir::new("operation-log");
+        let journal: Box<dyn Journal> = Box::new(MemoryJournal::new("notes"));
+        let log = OperationLog::new(&dir.join("notes.json"), 100, &journal);
+
+        log.record(added("First")).unwrap();
+        let operation = log.last_done().unwrap().unwrap();
+        log.mark_undone(operation).unwrap();
+        assert!(log.last_undone().unwrap().is_some());
+
+        log.record(added("Second")).unwrap();
+        assert!(log.last_undone().unwrap().is_none());
+        assert_eq!(log.last_done().unwrap().unwrap().description(), "Second");
+    }
+
+    #[test]
+    fn only_the_last_operations_are_kept() {
+        let dir = TempDir::new("operation-log");
+        let journal: Box<dyn Journal> = Box::new(MemoryJournal::new("notes"));
+        let log = OperationLog::new(&dir.join("notes.json"), 100, &journal);
+
+        for n in 0..MAX_OPERATIONS + 1 {
+            log.record(added(&format!("Note {}", n))).unwrap();
+        }
+
+        for _ in 0..MAX_OPERATIONS {
+            let operation = log.last_done().unwrap().unwrap();
+            log.mark_undone(operation).unwrap();
+        }
+        assert!(log.last_done().unwrap().is_none());
+        assert_eq!(log.last_undone().unwrap().unwrap().description(), "Note 1");
+    }
+
+    #[test]
+    fn marking_without_an_operation_changes_nothing() {
+        let dir = TempDir::new("operation-log");
+        let journal: Box<dyn Journal> = Box::new(MemoryJournal::new("notes"));
+        let log = OperationLog::new(&dir.join("notes.json"), 100, &journal);
+
+        log.mark_undone(added("Never done")).unwrap();
+        log.mark_redone(added("Never undone")).unwrap();
+        assert!(log.last_done().unwrap().is_none());
+        assert!(log.last_undone().unwrap().is_none());
+    }
+
+    #[test]
+    fn with_changes_keeps_the_description_and_time() {
+        let operation = added("Add entry");
+        let changed = operation.with_changes(vec![]);
+
+        assert_eq!(changed.description(), "Add entry");
+        assert_eq!(changed.done_at(), operation.done_at());
+        assert!(changed.changes().is_empty());
+    }
+}
@@ -0,0 +1,178 @@
+extern crate terminal_size;
+extern crate atty;
+
+use self::terminal_size::{terminal_size, Width};
+use chrono::*;
+use std::cmp;
+use std::env;
+use rjrn::entry::Entry;
+
+const DEFAULT_WIDTH: usize = 80;
+const ID_PREFIX_LEN: usize = 8;
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M";
+const HIGHLIGHT_START: &str = "\x1b[1;31m";
+const HIGHLIGHT_END: &str = "\x1b[0m";
+
+// Width of the terminal, `$COLUMNS` or 80 columns when it's not a terminal
+pub fn terminal_width() -> usize {
+    if let Some((Width(width), _)) = terminal_size() {
+        return width as usize;
+    }
+
+    env::var("COLUMNS").ok()
+        .and_then(|c| c.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+// Colours are used only when writing to a terminal
+pub fn stdout_is_tty() -> bool {
+    atty::is(atty::Stream::Stdout)
+}
+
+// Nothing was piped in
+pub fn stdin_is_tty() -> bool {
+    atty::is(atty::Stream::Stdin)
+}
+
+pub fn id_prefix(entry: &Entry) -> String {
+    entry.id().simple().to_string()[..ID_PREFIX_LEN].to_string()
+}
+
+pub fn local_date(date: DateTime<Utc>) -> String {
+    date.with_timezone(&Local).format(DATE_FORMAT).to_string()
+}
+
+// Title of the entry, or the first line of the content when it has none
+pub fn title(entry: &Entry) -> String {
+    match *entry.title() {
+        Some(ref title) if !title.trim().is_empty() => title.trim().to_string(),
+        _ => entry.content().lines().next().unwrap_or("").trim().to_string()
+    }
+}
+
+// `a1b2c3d4  2026-01-01 09:30  * Title  [work, home]` cut to `width`
+pub fn entry_line(entry: &Entry, width: usize) -> String {
+    summary_line(&id_prefix(entry), entry.created_at(), entry.starred(), &title(entry),
+                 entry.tags(), width)
+}
+
+// Byte range of the title in the `entry_line` of the entry, the part of it
+// that's left when the line was cut
+pub fn title_range(entry: &Entry, line: &str) -> (usize, usize) {
+    let start = format!("{}  {}  * ", id_prefix(entry), local_date(entry.created_at())).len();
+    let full = summary_line(&id_prefix(entry), entry.created_at(), entry.starred(), &title(entry),
+                            entry.tags(), usize::MAX);
+    let shown = if full == line { line.len() } else { line.len().saturating_sub(3) };
+
+    let end = cmp::min(start + title(entry).len(), shown);
+    if start >= end { (0, 0) } else { (start, end) }
+}
+
+pub fn summary_line(prefix: &str, date: DateTime<Utc>, starred: bool, title: &str,
+                    tags: &Vec<String>, width: usize) -> String {
+    let star = if starred { "*" } else { " " };
+    let mut line = format!("{}  {}  {} {}", prefix, local_date(date), star, title);
+
+    if !tags.is_empty() {
+        line = format!("{}  [{}]", line, tags.join(", "));
+    }
+
+    truncate(&line, width)
+}
+
+pub fn print_entry(entry: &Entry, full: bool, width: usize) {
+    println!("{}", entry_line(entry, width));
+
+    if full {
+        print_content(entry.content());
+    }
+}
+
+pub fn print_content(content: &str) {
+    for line in content.lines() {
+        println!("    {}", line);
+    }
+    println!();
+}
+
+pub fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width || width < 3 {
+        return text.to_string();
+    }
+
+    format!("{}...", text.chars().take(width - 3).collect::<String>())
+}
+
+// Marks the byte ranges of `hits` in bold red, text for pipes is left plain
+pub fn highlight(text: &str, hits: &Vec<(usize, usize)>, tty: bool) -> String {
+    if !tty {
+        return text.to_string();
+    }
+
+    let mut result = String::new();
+    let mut last = 0;
+
+    for &(from, to) in hits.iter() {
+        result.push_str(&text[last..from]);
+        result.push_str(HIGHLIGHT_START);
+        result.push_str(&text[from..to]);
+        result.push_str(HIGHLIGHT_END);
+        last = to;
+    }
+
+    result.push_str(&text[last..]);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use rjrn::entry::EntryBuilder;
+    use chrono::{TimeZone, Utc};
+    use super::{entry_line, local_date, summary_line, title_range, truncate};
+
+    #[test]
+    fn title_range_leaves_out_the_id_date_and_tags() {
+        let mut builder = EntryBuilder::new();
+        builder.content("Deploy the build".to_string()).tag("deploy");
+        let entry = builder.finalize().unwrap();
+
+        let line = entry_line(&entry, 200);
+        let (from, to) = title_range(&entry, &line);
+        assert_eq!(&line[from..to], "Deploy the build");
+        assert!(line[to..].contains("[deploy]"));
+    }
+
+    #[test]
+    fn title_range_ends_where_the_line_was_cut() {
+        let mut builder = EntryBuilder::new();
+        builder.content("Deploy the build to every server".to_string());
+        let entry = builder.finalize().unwrap();
+
+        let line = entry_line(&entry, 40);
+        let (from, to) = title_range(&entry, &line);
+        assert!(line.ends_with("..."));
+        assert_eq!(to, line.len() - 3);
+        assert!("Deploy the build".starts_with(&line[from..to]));
+
+        assert_eq!(title_range(&entry, &entry_line(&entry, 10)), (0, 0));
+    }
+
+    #[test]
+    fn summary_lines_fit_the_width() {
+        let date = Utc.with_ymd_and_hms(2026, 1, 1, 9, 30, 0).unwrap();
+        let tags = vec!["work".to_string(), "ci".to_string()];
+        let line = summary_line("a1b2c3d4", date, true, "Fixed the build", &tags, 200);
+        assert_eq!(line, format!("a1b2c3d4  {}  * Fixed the build  [work, ci]", local_date(date)));
+
+        let cut = summary_line("a1b2c3d4", date, false, "Fixed the build", &vec![], 36);
+        assert_eq!(cut.chars().count(), 36);
+        assert!(cut.ends_with("    Fix..."), "{}", cut);
+    }
+
+    #[test]
+    fn truncate_counts_characters() {
+        assert_eq!(truncate("Café crème", 7), "Café...");
+        assert_eq!(truncate("Café", 4), "Café");
+        assert_eq!(truncate("Café crème", 2), "Café crème");
+    }
+}
@@ -0,0 +1,40 @@
+//! Interactive questions shared by the `bootstrap_cli` of journal backends.
+
+use std::io;
+use std::env;
+
+pub fn read_line(question: &str) -> String {
+    println!("{}", question);
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)
+        .expect("Failed to read the answer");
+
+    answer.trim().to_string()
+}
+
+// Anything but `y`/`yes` is a no
+pub fn confirm(question: &str) -> bool {
+    let answer = read_line(question).to_lowercase();
+    answer == "y" || answer == "yes"
+}
+
+pub fn journal_name() -> String {
+    let name = read_line("Name of the journal (default):");
+
+    if name.is_empty() { "default".to_string() } else { name }
+}
+
+// Empty answer puts `default_file_name` in the home directory
+pub fn journal_path(default_file_name: &str) -> Result<String, String> {
+    let path = read_line(&format!("Path of the journal (~/{}):", default_file_name));
+
+    if path.is_empty() {
+        match env::home_dir() {
+            Some(ref p) => Ok(p.join(default_file_name).to_str().unwrap().to_string()),
+            None => Err("Impossible to get your home dir!".to_string())
+        }
+    } else {
+        Ok(path)
+    }
+}
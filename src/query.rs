@@ -0,0 +1,348 @@
+//! Small query language to filter entries, shared by the commands working
+//! on a set of entries. A query is a list of terms which all have to match:
+//!
+//!```text
+//!    tag:work starred:true after:2026-01-01 before:yesterday "deploy"
+//!```
+//!
+//! * `tag:<tag>` - entries with the tag, case-insensitive
+//! * `starred:true` / `starred:false`
+//! * `after:<date>` / `before:<date>` - created on that day or later / before that day
+//! * `updated_after:<date>` / `updated_before:<date>` - the same for the last update
+//! * `title:<text>` - text in the title
+//! * `journal:<name>` - entries of the journal with that name
+//! * `id:<prefix>` - entries with an id starting with the prefix
+//! * `<word>` or `"some text"` - text in the title or the content, case-insensitive
+//!
+//! Dates are `YYYY-MM-DD` in local time, `today`, `yesterday` or `tomorrow`.
+//! A term starting with `-` matches entries the term doesn't match. Keys
+//! without a value, like `tag:`, are errors.
+//!
+//!```
+//!    use rjrn::entry::EntryBuilder;
+//!    use rjrn::query::Query;
+//!
+//!    let mut builder = EntryBuilder::new();
+//!    let entry = builder.content("Deploy went fine".to_string())
+//!        .tags(vec!["work".to_string()])
+//!        .starred(true)
+//!        .finalize().unwrap();
+//!
+//!    let query = Query::parse("tag:work starred:true after:2020-01-01 \"deploy\"").unwrap();
+//!    assert!(query.matches(&entry, "default"));
+//!    assert!(!Query::parse("-tag:work").unwrap().matches(&entry, "default"));
+//!    assert!(!Query::parse("journal:home").unwrap().matches(&entry, "default"));
+//!    assert!(Query::parse("before:someday").is_err());
+//!    assert!(Query::parse("tag:").is_err());
+//!```
+
+use chrono::*;
+
+use entry::Entry;
+use sqlite_journal::EntryFilter;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    Tag(String),
+    Starred(bool),
+    CreatedAfter(DateTime<Utc>),
+    CreatedBefore(DateTime<Utc>),
+    UpdatedAfter(DateTime<Utc>),
+    UpdatedBefore(DateTime<Utc>),
+    Title(String),
+    Journal(String),
+    IdPrefix(String),
+    Text(String),
+    Not(Box<Term>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    terms: Vec<Term>,
+}
+
+impl Query {
+    // Matches every entry
+    pub fn all() -> Query {
+        Query { terms: vec![] }
+    }
+
+    pub fn parse(query: &str) -> Result<Query, String> {
+        let mut terms = vec![];
+        for word in split(query)?.iter() {
+            terms.push(parse_term(word)?);
+        }
+
+        Ok(Query { terms })
+    }
+
+    // Each argument is one term, so arguments with spaces don't need extra quotes
+    pub fn from_args(args: &Vec<String>) -> Result<Query, String> {
+        let mut terms = vec![];
+        for arg in args.iter() {
+            let term = if arg.chars().any(|c| c.is_whitespace()) && !arg.contains('"') {
+                Term::Text(arg.to_lowercase())
+            } else {
+                parse_term(arg)?
+            };
+            terms.push(term);
+        }
+
+        Ok(Query { terms })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub fn matches(&self, entry: &Entry, journal: &str) -> bool {
+        self.terms.iter().all(|term| term_matches(term, entry, journal))
+    }
+
+    // False when no entry of the journal could match, so it doesn't have to be read
+    pub fn matches_journal(&self, journal: &str) -> bool {
+        self.terms.iter().all(|term| match *term {
+            Term::Journal(ref name) => name == journal,
+            Term::Not(ref term) => match **term {
+                Term::Journal(ref name) => name != journal,
+                _ => true
+            },
+            _ => true
+        })
+    }
+
+    // Conditions a SQLite journal can check with its indexes. Entries it returns
+    // still have to be matched, only the first tag is used and negated terms
+    // are left out. Tags with other than ASCII letters aren't compared there
+    // as SQLite only ignores ASCII case.
+    pub fn entry_filter(&self) -> EntryFilter {
+        let mut filter = EntryFilter::default();
+        for term in self.terms.iter() {
+            match *term {
+                Term::Tag(ref tag) if filter.tag.is_none() && tag.is_ascii() => {
+                    filter.tag = Some(tag.clone())
+                },
+                Term::Starred(starred) => filter.starred = Some(starred),
+                Term::CreatedAfter(date) => {
+                    filter.created_after = Some(filter.created_after.map_or(date, |d| d.max(date)))
+                },
+                Term::CreatedBefore(date) => {
+                    filter.created_before = Some(filter.created_before.map_or(date, |d| d.min(date)))
+                },
+                _ => {}
+            }
+        }
+        filter
+    }
+
+    // Whether the query picks journals by name
+    pub fn has_journal(&self) -> bool {
+        self.terms.iter().any(|term| match *term {
+            Term::Journal(_) => true,
+            Term::Not(ref term) => matches!(**term, Term::Journal(_)),
+            _ => false
+        })
+    }
+}
+
+fn parse_term(word: &str) -> Result<Term, String> {
+    if word.starts_with('-') && word.len() > 1 {
+        return Ok(Term::Not(Box::new(parse_term(&word[1..])?)));
+    }
+
+    if word.starts_with('"') {
+        return Ok(Term::Text(word.trim_matches('"').to_lowercase()));
+    }
+
+    let (key, value) = match word.find(':') {
+        Some(i) => (&word[..i], &word[i + 1..]),
+        None => return Ok(Term::Text(word.to_lowercase()))
+    };
+
+    // `tag:` alone would match nothing or everything, it's more likely a typo
+    let text = value.trim_matches('"');
+    let needs_text = ["tag", "title", "journal", "id"].contains(&key);
+    if needs_text && text.trim_start_matches(['#', '@', '-']).is_empty() {
+        return Err(format!("Expected a value after `{}:` in `{}`", key, word));
+    }
+
+    match key {
+        "tag" => Ok(Term::Tag(text.trim_start_matches(['#', '@']).to_lowercase())),
+        "starred" => match value {
+            "true" | "yes" => Ok(Term::Starred(true)),
+            "false" | "no" => Ok(Term::Starred(false)),
+            _ => Err(format!("Expected true or false in `{}`", word))
+        },
+        "after" => Ok(Term::CreatedAfter(parse_date(value)?)),
+        "before" => Ok(Term::CreatedBefore(parse_date(value)?)),
+        "updated_after" => Ok(Term::UpdatedAfter(parse_date(value)?)),
+        "updated_before" => Ok(Term::UpdatedBefore(parse_date(value)?)),
+        "title" => Ok(Term::Title(text.to_lowercase())),
+        "journal" => Ok(Term::Journal(text.to_string())),
+        "id" => Ok(Term::IdPrefix(value.replace("-", "").to_lowercase())),
+        // Text with a colon, like `10:30`
+        _ => Ok(Term::Text(word.to_lowercase()))
+    }
+}
+
+// Start of the day in local time
+fn parse_date(value: &str) -> Result<DateTime<Utc>, String> {
+    let today = Local::now().date_naive();
+    let day = match value {
+        "today" => today,
+        "yesterday" => today - Duration::days(1),
+        "tomorrow" => today + Duration::days(1),
+        _ => match NaiveDate::parse_from_str(value, DATE_FORMAT) {
+            Ok(date) => date,
+            Err(_) => return Err(format!("Invalid date `{}`, expected YYYY-MM-DD, today, \
+                                          yesterday or tomorrow", value))
+        }
+    };
+
+    day.and_hms_opt(0, 0, 0)
+        .and_then(|midnight| Local.from_local_datetime(&midnight).earliest())
+        .map(|date| date.with_timezone(&Utc))
+        .ok_or(format!("Invalid date: {}", value))
+}
+
+fn term_matches(term: &Term, entry: &Entry, journal: &str) -> bool {
+    match *term {
+        Term::Tag(ref tag) => entry.tags().iter().any(|t| t.to_lowercase() == *tag),
+        Term::Starred(starred) => entry.starred() == starred,
+        Term::CreatedAfter(ref date) => entry.created_at() >= *date,
+        Term::CreatedBefore(ref date) => entry.created_at() < *date,
+        Term::UpdatedAfter(ref date) => entry.updated_at() >= *date,
+        Term::UpdatedBefore(ref date) => entry.updated_at() < *date,
+        Term::Title(ref text) => entry.title().as_ref()
+            .map(|t| t.to_lowercase().contains(text.as_str()))
+            .unwrap_or(false),
+        Term::Journal(ref name) => name == journal,
+        Term::IdPrefix(ref prefix) => entry.id().simple().to_string().starts_with(prefix.as_str()),
+        Term::Text(ref text) => {
+            entry.content().to_lowercase().contains(text.as_str()) ||
+                entry.title().as_ref().map(|t| t.to_lowercase().contains(text.as_str())).unwrap_or(false)
+        },
+        Term::Not(ref term) => !term_matches(term, entry, journal),
+    }
+}
+
+// Splits on whitespace outside of double quotes, quotes are kept on the words
+fn split(query: &str) -> Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut current = String::new();
+    let mut in_quotes = false;
+
+    for c in query.chars() {
+        if c == '"' {
+            in_quotes = !in_quotes;
+            current.push(c);
+        } else if c.is_whitespace() && !in_quotes {
+            if !current.is_empty() {
+                words.push(current.clone());
+                current.clear();
+            }
+        } else {
+            current.push(c);
+        }
+    }
+
+    if in_quotes {
+        return Err(format!("Unclosed quote in query: {}", query));
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::*;
+    use entry::{Entry, EntryBuilder};
+    use super::{parse_date, Query};
+
+    fn entry(content: &str, created_at: DateTime<Utc>) -> Entry {
+        let mut builder = EntryBuilder::new();
+        builder.content(content.to_string())
+            .created_at(created_at)
+            .updated_at(created_at)
+            .tag("work");
+        builder.finalize().unwrap()
+    }
+
+    fn matches(query: &str, entry: &Entry) -> bool {
+        Query::parse(query).unwrap().matches(entry, "notes")
+    }
+
+    #[test]
+    fn negated_terms_match_what_the_term_doesnt() {
+        let entry = entry("Deploy went fine", Utc::now());
+        assert!(!matches("-tag:work", &entry));
+        assert!(matches("-tag:home", &entry));
+        assert!(matches("-starred:true", &entry));
+        assert!(!matches("-deploy", &entry));
+        assert!(matches("-\"deploy failed\"", &entry));
+        assert!(!matches("-journal:notes", &entry));
+    }
+
+    #[test]
+    fn quoted_terms_are_matched_as_a_whole() {
+        let entry = entry("Deploy went fine", Utc::now());
+        assert!(matches("\"deploy went\"", &entry));
+        assert!(!matches("\"deploy fine\"", &entry));
+        assert!(matches("title:\"went fine\" tag:\"#work\"", &entry));
+        assert!(Query::parse("\"deploy went").is_err());
+
+        let args = vec!["deploy went".to_string(), "tag:work".to_string()];
+        assert!(Query::from_args(&args).unwrap().matches(&entry, "notes"));
+    }
+
+    #[test]
+    fn relative_dates_are_days_in_local_time() {
+        let now = entry("Today's note", Utc::now());
+        assert!(matches("after:today before:tomorrow", &now));
+        assert!(!matches("before:yesterday", &now));
+        assert!(!matches("before:today", &now));
+
+        let old = entry("Old note", Utc::now() - Duration::days(3));
+        assert!(matches("before:yesterday", &old));
+        assert!(!matches("after:yesterday", &old));
+        assert!(matches("after:2000-01-01 updated_before:today", &old));
+    }
+
+    #[test]
+    fn malformed_terms_are_errors() {
+        for query in ["after:notadate", "before:2026-13-01", "updated_after:", "starred:maybe",
+                      "tag:", "tag:#", "title:\"\"", "journal:", "id:"].iter() {
+            assert!(Query::parse(query).is_err(), "{} was accepted", query);
+        }
+        assert!(Query::parse("10:30").is_ok());
+    }
+
+    #[test]
+    fn entry_filters_keep_the_narrowest_conditions() {
+        let filter = Query::parse("tag:work tag:ci starred:true after:2026-01-01 after:2026-02-01 \
+                                   before:2026-05-01 before:2026-04-01 -after:2026-03-01").unwrap()
+            .entry_filter();
+        assert_eq!(filter.tag, Some("work".to_string()));
+        assert_eq!(filter.starred, Some(true));
+        assert_eq!(filter.created_after, Some(parse_date("2026-02-01").unwrap()));
+        assert_eq!(filter.created_before, Some(parse_date("2026-04-01").unwrap()));
+
+        let filter = Query::parse("-tag:work tag:café -starred:true").unwrap().entry_filter();
+        assert!(filter.tag.is_none() && filter.starred.is_none());
+    }
+
+    #[test]
+    fn journal_terms_pick_the_journals_to_read() {
+        let query = Query::parse("journal:work deploy").unwrap();
+        assert!(query.has_journal());
+        assert!(query.matches_journal("work"));
+        assert!(!query.matches_journal("notes"));
+        assert!(!Query::parse("-journal:work").unwrap().matches_journal("work"));
+        assert!(!Query::parse("deploy").unwrap().has_journal());
+    }
+}
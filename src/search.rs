@@ -0,0 +1,116 @@
+//! Full-text search over the title and the content of entries.
+//!
+//!```
+//!    use rjrn::search::{Matcher, Mode};
+//!
+//!    let matcher = Matcher::new("deploy", Mode::Word).unwrap();
+//!    assert_eq!(matcher.find_all("Deploy done, deployment next"), vec![(0, 6)]);
+//!
+//!    let matcher = Matcher::new("deploy", Mode::Substring).unwrap();
+//!    assert_eq!(matcher.find_all("Deploy done, deployment next").len(), 2);
+//!
+//!    let matcher = Matcher::new("#deploy", Mode::Word).unwrap();
+//!    assert_eq!(matcher.find_all("Done #deploy, #deployment next"), vec![(5, 12)]);
+//!
+//!    assert!(Matcher::new("(", Mode::Regex).is_err());
+//!```
+
+use regex::{self, Regex, RegexBuilder};
+
+use entry::Entry;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    // Case-insensitive text anywhere in a word
+    Substring,
+    // Case-insensitive whole words only
+    Word,
+    // The pattern is a regular expression, matched as given
+    Regex,
+}
+
+#[derive(Debug)]
+pub struct Matcher {
+    regex: Regex,
+}
+
+impl Matcher {
+    pub fn new(pattern: &str, mode: Mode) -> Result<Matcher, String> {
+        let (pattern, case_insensitive) = match mode {
+            Mode::Substring => (regex::escape(pattern), true),
+            Mode::Word => (word_pattern(pattern), true),
+            Mode::Regex => (pattern.to_string(), false),
+        };
+
+        RegexBuilder::new(&pattern)
+            .case_insensitive(case_insensitive)
+            .build()
+            .map(|regex| Matcher { regex })
+            .map_err(|why| format!("Invalid search pattern: {}", why))
+    }
+
+    // Byte ranges of every hit in the text
+    pub fn find_all(&self, text: &str) -> Vec<(usize, usize)> {
+        self.regex.find_iter(text)
+            .filter(|m| m.start() < m.end())
+            .map(|m| (m.start(), m.end()))
+            .collect()
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        !self.find_all(text).is_empty()
+    }
+
+    pub fn matches_entry(&self, entry: &Entry) -> bool {
+        let title = entry.title().as_ref().map(|t| self.is_match(t)).unwrap_or(false);
+        title || self.is_match(entry.content())
+    }
+}
+
+// `\b` only holds next to a word character, so patterns starting or ending
+// with something like `#` only get a boundary on their other side
+fn word_pattern(pattern: &str) -> String {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let start = if pattern.chars().next().map(&is_word).unwrap_or(false) { r"\b" } else { "" };
+    let end = if pattern.chars().last().map(&is_word).unwrap_or(false) { r"\b" } else { "" };
+
+    format!("{}{}{}", start, regex::escape(pattern), end)
+}
+
+#[cfg(test)]
+mod tests {
+    use entry::EntryBuilder;
+    use test_support::entry;
+    use super::{Matcher, Mode};
+
+    #[test]
+    fn substrings_and_words_are_plain_text() {
+        let matcher = Matcher::new("c++ (v2)", Mode::Substring).unwrap();
+        assert_eq!(matcher.find_all("Moved to C++ (V2) today"), vec![(9, 17)]);
+
+        let matcher = Matcher::new("café", Mode::Word).unwrap();
+        assert!(matcher.is_match("Coffee at the CAFÉ."));
+        assert!(!matcher.is_match("Coffee at the cafés"));
+    }
+
+    #[test]
+    fn regexes_are_matched_as_given() {
+        let matcher = Matcher::new(r"v\d+", Mode::Regex).unwrap();
+        assert_eq!(matcher.find_all("Shipped v2, then v10"), vec![(8, 10), (17, 20)]);
+        assert!(!matcher.is_match("Shipped V2"));
+
+        // Empty matches aren't hits
+        assert!(!Matcher::new("x*", Mode::Regex).unwrap().is_match("deploy"));
+    }
+
+    #[test]
+    fn entries_match_by_title_or_content() {
+        let matcher = Matcher::new("deploy", Mode::Word).unwrap();
+        assert!(matcher.matches_entry(&entry("Went fine. Deploy of the API")));
+        assert!(!matcher.matches_entry(&entry("Deployment went fine")));
+
+        let titled = EntryBuilder::new().content("Went fine".to_string())
+            .title("Deploy".to_string()).finalize().unwrap();
+        assert!(matcher.matches_entry(&titled));
+    }
+}
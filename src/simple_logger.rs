@@ -1,28 +1,29 @@
-use log::{self, LogRecord, LogMetadata, LogLevelFilter, SetLoggerError};
+use log::{self, Record, Metadata, LevelFilter, SetLoggerError};
 struct SimpleLogger;
 
+static LOGGER: SimpleLogger = SimpleLogger;
+
 impl log::Log for SimpleLogger {
-    fn enabled(&self, _metadata: &LogMetadata) -> bool {
-        true // metadata.level() <= LogLevel::Trace
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true // metadata.level() <= Level::Trace
     }
 
-    fn log(&self, record: &LogRecord) {
+    fn log(&self, record: &Record) {
         if self.enabled(record.metadata()) {
             println!("{} - {}", record.level(), record.args());
         }
     }
+
+    fn flush(&self) {}
 }
 
 
 pub fn init(verbose: bool) -> Result<(), SetLoggerError> {
     let level = if verbose {
-        LogLevelFilter::Debug
+        LevelFilter::Debug
     } else {
-        LogLevelFilter::Info
+        LevelFilter::Info
     };
 
-    log::set_logger(|max_log_level| {
-        max_log_level.set(level);
-        Box::new(SimpleLogger)
-    })
+    log::set_logger(&LOGGER).map(|()| log::set_max_level(level))
 }
@@ -0,0 +1,442 @@
+//! SQLite Journal keeps entries in a local SQLite database. Dates, tags and
+//! the star are indexed, so `filter_entries`, and queries through it, don't
+//! have to read the whole journal into memory. Earlier versions of an entry are kept as JSON in
+//! `entry_revisions` until `compact` drops them.
+//!
+//! Usage for bootstraping and saving:
+//!
+//!```
+//!    use rjrn::journal::Journal;
+//!    use rjrn::sqlite_journal::SqliteJournal;
+//!    let journal = SqliteJournal::new("name", "path.sqlite");
+//!    assert_eq!(journal, SqliteJournal::from_json(&journal.to_json()).unwrap());
+//!```
+
+use rusqlite::{self, Connection, ToSql, params_from_iter};
+use serde_json::{self, Map, Value};
+use chrono::*;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use journal::{config_string, file_with_siblings, Journal, JournalFactory, JournalOptions, JournalRegistry};
+use entry::{Entry, EntryBuilder, Revision};
+use prompt;
+use query::Query;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS entries (
+    id TEXT PRIMARY KEY NOT NULL,
+    title TEXT,
+    content TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    starred INTEGER NOT NULL DEFAULT 0
+);
+CREATE TABLE IF NOT EXISTS entry_tags (
+    entry_id TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (entry_id, position)
+);
+CREATE TABLE IF NOT EXISTS entry_revisions (
+    entry_id TEXT PRIMARY KEY NOT NULL,
+    revisions TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS entries_created_at ON entries (created_at);
+CREATE INDEX IF NOT EXISTS entries_updated_at ON entries (updated_at);
+CREATE INDEX IF NOT EXISTS entries_starred ON entries (starred);
+CREATE INDEX IF NOT EXISTS entry_tags_tag ON entry_tags (tag);
+";
+
+// Fixed width, so dates stored as text sort the same way as in time
+const DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.9f+00:00";
+
+/// Conditions for `SqliteJournal::filter_entries`, `None` matches everything.
+/// Tags are compared ignoring ASCII case.
+#[derive(Debug, Default, Clone)]
+pub struct EntryFilter {
+    pub starred: Option<bool>,
+    pub tag: Option<String>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug)]
+pub struct SqliteJournal {
+    name: String,
+    path: String,
+    default: bool,
+}
+
+impl Journal for SqliteJournal {
+    fn to_json(&self) -> Value {
+        let mut d = Map::new();
+        d.insert("name".to_string(), Value::String(self.name.clone()));
+        d.insert("path".to_string(), Value::String(self.path.clone()));
+        d.insert("type".to_string(), Value::String("SqliteJournal".to_string()));
+        d.insert("default".to_string(), Value::Bool(self.default));
+        Value::Object(d)
+    }
+
+    fn set_default(&mut self) {
+        self.default = true
+    }
+
+    fn is_default(&self) -> bool {
+        self.default
+    }
+
+    fn name(&self) -> &String {
+        &self.name
+    }
+
+    fn entries(&self) -> Result<Vec<Box<Entry>>, String> {
+        self.filter_entries(&EntryFilter::default())
+    }
+
+    fn query_entries(&self, query: &Query) -> Result<Vec<Box<Entry>>, String> {
+        Ok(self.filter_entries(&query.entry_filter())?
+            .into_iter()
+            .filter(|e| query.matches(e, &self.name))
+            .collect())
+    }
+
+    fn upsert_entry(&self, entry: Entry) -> Result<Uuid, String> {
+        debug!("Add entry with title:: {:?} to - {:?}", entry.title(), self.path);
+        let mut connection = self.connection()?;
+        let tx = connection.transaction().map_err(db_error)?;
+        let id = entry.id().hyphenated().to_string();
+
+        let mut entry = entry;
+        let params: Vec<Box<dyn ToSql>> = vec![Box::new(id.clone())];
+        if let Some(previous) = self.select(&tx, "id = ?", &params)?.first() {
+            entry.keep_history_of(previous);
+        }
+
+        tx.execute("INSERT INTO entries (id, title, content, created_at, updated_at, starred)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                    ON CONFLICT (id) DO UPDATE SET
+                      title = excluded.title,
+                      content = excluded.content,
+                      created_at = excluded.created_at,
+                      updated_at = excluded.updated_at,
+                      starred = excluded.starred",
+                   rusqlite::params![id,
+                                     entry.title(),
+                                     entry.content(),
+                                     format_date(entry.created_at()),
+                                     format_date(entry.updated_at()),
+                                     entry.starred()])
+        .map_err(db_error)?;
+        tx.execute("DELETE FROM entry_tags WHERE entry_id = ?1", [&id]).map_err(db_error)?;
+
+        for (position, tag) in entry.tags().iter().enumerate() {
+            tx.execute("INSERT INTO entry_tags (entry_id, position, tag) VALUES (?1, ?2, ?3)",
+                       rusqlite::params![id, position as i64, tag])
+            .map_err(db_error)?;
+        }
+
+        tx.execute("DELETE FROM entry_revisions WHERE entry_id = ?1", [&id]).map_err(db_error)?;
+        if !entry.revisions().is_empty() {
+            tx.execute("INSERT INTO entry_revisions (entry_id, revisions) VALUES (?1, ?2)",
+                       rusqlite::params![id, serde_json::to_string(entry.revisions()).unwrap()])
+            .map_err(db_error)?;
+        }
+
+        tx.commit().map_err(db_error)?;
+        Ok(*entry.id())
+    }
+
+    fn remove_entries(&self, ids: &Vec<Uuid>) -> Result<(), String> {
+        let mut connection = self.connection()?;
+        let tx = connection.transaction().map_err(db_error)?;
+
+        for id in ids.iter() {
+            let id = id.hyphenated().to_string();
+            tx.execute("DELETE FROM entry_tags WHERE entry_id = ?1", [&id]).map_err(db_error)?;
+            tx.execute("DELETE FROM entry_revisions WHERE entry_id = ?1", [&id]).map_err(db_error)?;
+            tx.execute("DELETE FROM entries WHERE id = ?1", [&id]).map_err(db_error)?;
+        }
+
+        tx.commit().map_err(db_error)
+    }
+
+    fn files(&self) -> Vec<PathBuf> {
+        file_with_siblings(&self.path, &["-wal", "-shm", "-journal"])
+    }
+
+    fn compact(&self) -> Result<(), String> {
+        self.connection()?.execute_batch("DELETE FROM entry_revisions; VACUUM").map_err(db_error)
+    }
+}
+
+impl SqliteJournal {
+    pub fn new(name: &str, path: &str) -> SqliteJournal {
+        SqliteJournal {
+            name: name.to_string(),
+            path: path.to_string(),
+            default: false,
+        }
+    }
+
+    pub fn from_json(j: &Value) -> Result<SqliteJournal, String> {
+        Ok(SqliteJournal {
+            name: config_string(j, "name")?,
+            path: config_string(j, "path")?,
+            default: j.get("default").and_then(|d| d.as_bool()).unwrap_or(false),
+        })
+    }
+
+    // Used by the CLI when the user want to create a new journal of that type
+    pub fn bootstrap_cli() -> Result<SqliteJournal, String> {
+        let name = prompt::journal_name();
+        let path = prompt::journal_path(&format!("rjrn-{}.sqlite", name))?;
+
+        SqliteJournal::create(&name, &path)
+    }
+
+    // New journal with its database and tables created
+    pub fn create(name: &str, path: &str) -> Result<SqliteJournal, String> {
+        let journal = SqliteJournal::new(name, path);
+        journal.connection()?;
+        Ok(journal)
+    }
+
+    /// Entries matching all conditions of the filter, in the order they were added.
+    pub fn filter_entries(&self, filter: &EntryFilter) -> Result<Vec<Box<Entry>>, String> {
+        let mut conditions: Vec<&str> = vec![];
+        let mut params: Vec<Box<dyn ToSql>> = vec![];
+
+        if let Some(starred) = filter.starred {
+            conditions.push("starred = ?");
+            params.push(Box::new(starred));
+        }
+        if let Some(ref tag) = filter.tag {
+            conditions.push("id IN (SELECT entry_id FROM entry_tags WHERE tag = ? COLLATE NOCASE)");
+            params.push(Box::new(tag.clone()));
+        }
+        if let Some(after) = filter.created_after {
+            conditions.push("created_at >= ?");
+            params.push(Box::new(format_date(after)));
+        }
+        if let Some(before) = filter.created_before {
+            conditions.push("created_at < ?");
+            params.push(Box::new(format_date(before)));
+        }
+
+        let where_clause = if conditions.is_empty() {
+            "1".to_string()
+        } else {
+            conditions.join(" AND ")
+        };
+
+        self.select(&self.connection()?, &where_clause, &params)
+    }
+
+    fn select(&self, connection: &Connection, where_clause: &str, params: &Vec<Box<dyn ToSql>>)
+              -> Result<Vec<Box<Entry>>, String> {
+        let mut tags = self.tags_where(connection, where_clause, params)?;
+        let mut revisions = self.revisions_where(connection, where_clause, params)?;
+        let mut statement = connection.prepare(
+       &format!("SELECT id, title, content, created_at, updated_at, starred
+                 FROM entries WHERE {} ORDER BY rowid", where_clause))
+       .map_err(db_error)?;
+
+        let rows = statement.query_map(params_from_iter(params.iter()), |row| {
+       Ok((row.get::<_, String>(0)?,
+           row.get::<_, Option<String>>(1)?,
+           row.get::<_, String>(2)?,
+           row.get::<_, String>(3)?,
+           row.get::<_, String>(4)?,
+           row.get::<_, bool>(5)?))
+   }).map_err(db_error)?;
+
+        let mut entries = vec![];
+        for row in rows {
+            let (id, title, content, created_at, updated_at, starred) = row.map_err(db_error)?;
+            let mut builder = EntryBuilder::new();
+            builder.id(parse_id(&id)?)
+                .raw_content(content)
+                .created_at(parse_date(&created_at)?)
+                .updated_at(parse_date(&updated_at)?)
+                .starred(starred)
+                .tags(tags.remove(&id).unwrap_or(vec![]));
+
+            if let Some(title) = title {
+                builder.title(title);
+            }
+
+            let mut entry = builder.finalize()?;
+            entry.set_revisions(revisions.remove(&id).unwrap_or(vec![]));
+            entries.push(Box::new(entry));
+        }
+
+        Ok(entries)
+    }
+
+    fn tags_where(&self, connection: &Connection, where_clause: &str, params: &Vec<Box<dyn ToSql>>)
+                  -> Result<HashMap<String, Vec<String>>, String> {
+        let mut statement = connection.prepare(
+       &format!("SELECT entry_id, tag FROM entry_tags
+                 WHERE entry_id IN (SELECT id FROM entries WHERE {})
+                 ORDER BY entry_id, position", where_clause))
+       .map_err(db_error)?;
+
+        let rows = statement.query_map(params_from_iter(params.iter()), |row| {
+       Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+   }).map_err(db_error)?;
+
+        let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+        for row in rows {
+            let (id, tag) = row.map_err(db_error)?;
+            tags.entry(id).or_insert(vec![]).push(tag);
+        }
+
+        Ok(tags)
+    }
+
+    fn revisions_where(&self, connection: &Connection, where_clause: &str, params: &Vec<Box<dyn ToSql>>)
+                       -> Result<HashMap<String, Vec<Revision>>, String> {
+        let mut statement = connection.prepare(
+       &format!("SELECT entry_id, revisions FROM entry_revisions
+                 WHERE entry_id IN (SELECT id FROM entries WHERE {})", where_clause))
+       .map_err(db_error)?;
+
+        let rows = statement.query_map(params_from_iter(params.iter()), |row| {
+       Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+   }).map_err(db_error)?;
+
+        let mut revisions = HashMap::new();
+        for row in rows {
+            let (id, json) = row.map_err(db_error)?;
+            let list: Vec<Revision> = serde_json::from_str(&json)
+                .map_err(|why| format!("Couldn't read the revisions of {} because: {}", id, why))?;
+            revisions.insert(id, list);
+        }
+
+        Ok(revisions)
+    }
+
+    fn connection(&self) -> Result<Connection, String> {
+        let connection = Connection::open(&self.path)
+                         .map_err(|why| format!("Couldn't open journal database because: {}", why))?;
+        connection.execute_batch(SCHEMA).map_err(db_error)?;
+        Ok(connection)
+    }
+}
+
+fn db_error(why: rusqlite::Error) -> String {
+    format!("Journal database error: {}", why)
+}
+
+fn format_date(date: DateTime<Utc>) -> String {
+    date.format(DATE_FORMAT).to_string()
+}
+
+fn parse_date(date: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::<Utc>::from_str(date)
+        .map_err(|_| format!("Couldn't parse date from the journal database: {}", date))
+}
+
+fn parse_id(id: &str) -> Result<Uuid, String> {
+    Uuid::parse_str(id)
+        .map_err(|_| format!("Couldn't parse entry id from the journal database: {}", id))
+}
+
+pub struct SqliteJournalFactory;
+
+impl JournalFactory for SqliteJournalFactory {
+    fn kind(&self) -> &'static str {
+        "SqliteJournal"
+    }
+
+    fn description(&self) -> &'static str {
+        "SQLite Journal"
+    }
+
+    fn bootstrap_cli(&self, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        Ok(Box::new(SqliteJournal::bootstrap_cli()?))
+    }
+
+    fn from_json(&self, journal: &Value, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        Ok(Box::new(SqliteJournal::from_json(journal)?))
+    }
+
+    fn from_options(&self, options: &JournalOptions, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        Ok(Box::new(SqliteJournal::create(&options.name, options.path()?)?))
+    }
+}
+
+impl PartialEq for SqliteJournal {
+     fn eq(&self, other: &SqliteJournal) -> bool {
+         self.path == other.path
+     }
+ }
+
+#[cfg(test)]
+mod tests {
+    use entry::{Entry, EntryBuilder};
+    use journal::Journal;
+    use std::fs;
+    use query::Query;
+    use test_support::{entry, TempDir};
+    use super::{EntryFilter, SqliteJournal};
+
+    fn journal(dir: &TempDir) -> SqliteJournal {
+        fs::create_dir_all(dir.join("")).unwrap();
+        SqliteJournal::create("notes", &dir.join("journal.sqlite").to_string_lossy()).unwrap()
+    }
+
+    fn edited(entry: &Entry, content: &str) -> Entry {
+        EntryBuilder::from_entry(entry).raw_content(content.to_string()).finalize().unwrap()
+    }
+
+    #[test]
+    fn updates_keep_revisions_until_compact() {
+        let dir = TempDir::new("sqlite");
+        let journal = journal(&dir);
+        let milk = entry("Milk");
+        let bread = entry("Bread");
+        journal.upsert_entry(milk.clone()).unwrap();
+        journal.upsert_entry(bread.clone()).unwrap();
+        journal.upsert_entry(edited(&milk, "Milk and eggs")).unwrap();
+        journal.upsert_entry(edited(&bread, "Rye bread")).unwrap();
+        journal.upsert_entry(edited(&bread, "Rye bread")).unwrap();
+
+        let entries = journal.entries().unwrap();
+        assert_eq!(entries[0].content(), "Milk and eggs");
+        assert_eq!(entries[0].revisions()[0].content(), "Milk");
+        assert_eq!(entries[1].revisions().len(), 1);
+
+        journal.remove_entries(&vec![*milk.id()]).unwrap();
+        journal.upsert_entry(milk.clone()).unwrap();
+        assert!(journal.entries().unwrap()[1].revisions().is_empty());
+
+        journal.compact().unwrap();
+        assert!(journal.entries().unwrap().iter().all(|e| e.revisions().is_empty()));
+    }
+
+    #[test]
+    fn filters_and_queries_read_matching_entries() {
+        let dir = TempDir::new("sqlite");
+        let journal = journal(&dir);
+        let mut builder = EntryBuilder::new();
+        builder.content("Deploy #Work".to_string()).tags(vec!["Work".to_string()]).starred(true);
+        journal.upsert_entry(builder.finalize().unwrap()).unwrap();
+        journal.upsert_entry(entry("Groceries #home")).unwrap();
+        journal.upsert_entry(entry("Review #work")).unwrap();
+
+        let mut filter = EntryFilter { tag: Some("work".to_string()), ..EntryFilter::default() };
+        assert_eq!(journal.filter_entries(&filter).unwrap().len(), 2);
+        filter.starred = Some(true);
+        assert_eq!(journal.filter_entries(&filter).unwrap()[0].content(), "Deploy #Work");
+
+        let found = journal.query_entries(&Query::parse("tag:work -starred:true").unwrap()).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].content(), "Review #work");
+        assert!(journal.query_entries(&Query::parse("tag:work after:tomorrow").unwrap()).unwrap().is_empty());
+        assert_eq!(journal.query_entries(&Query::parse("journal:notes").unwrap()).unwrap().len(), 3);
+    }
+}
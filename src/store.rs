@@ -0,0 +1,136 @@
+//! Where the trash and the operation log of a journal keep their text: a file
+//! next to the config, or memory for configs that aren't saved in a file, so
+//! removing, restoring and undoing work without touching the disk.
+//!
+//!```
+//!    use rjrn::store::Store;
+//!
+//!    let store = Store::memory();
+//!    assert_eq!(store.read().unwrap(), "");
+//!    store.update(|text| Ok(format!("{}changed", text))).unwrap();
+//!    assert_eq!(store.read().unwrap(), "changed");
+//!    assert!(store.path().is_none());
+//!```
+
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+
+use atomic_write;
+use file_lock::FileLock;
+
+#[derive(Debug, Clone)]
+pub enum Store {
+    // Replaced atomically under `<path>.lock`, a missing file is empty
+    File { path: PathBuf, lock_timeout_ms: u64 },
+    // Clones share the text
+    Memory(Rc<RefCell<String>>),
+}
+
+impl Store {
+    pub fn file(path: &Path, lock_timeout_ms: u64) -> Store {
+        Store::File { path: path.to_path_buf(), lock_timeout_ms }
+    }
+
+    pub fn memory() -> Store {
+        Store::Memory(Rc::new(RefCell::new(String::new())))
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        match *self {
+            Store::File { ref path, .. } => Some(path),
+            Store::Memory(_) => None
+        }
+    }
+
+    // The file, or `memory`, for messages
+    pub fn describe(&self) -> String {
+        match *self {
+            Store::File { ref path, .. } => path.display().to_string(),
+            Store::Memory(_) => "memory".to_string()
+        }
+    }
+
+    pub fn read(&self) -> Result<String, String> {
+        let path = match *self {
+            Store::File { ref path, .. } => path,
+            Store::Memory(ref text) => return Ok(text.borrow().clone())
+        };
+
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(_) => return Ok(String::new())
+        };
+
+        let mut content = String::new();
+        file.read_to_string(&mut content)
+        .map_err(|why| format!("Couldn't read {} because: {}",
+                               path.display(), why))?;
+        Ok(content)
+    }
+
+    // Replaces the text with what `change` makes of it, no one else changes it
+    // in between. The directory of the file is created on first use.
+    pub fn update<F: FnOnce(&str) -> Result<String, String>>(&self, change: F) -> Result<(), String> {
+        let (path, lock_timeout_ms) = match *self {
+            Store::File { ref path, lock_timeout_ms } => (path, lock_timeout_ms),
+            Store::Memory(ref text) => {
+                let changed = change(&text.borrow())?;
+                *text.borrow_mut() = changed;
+                return Ok(());
+            }
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+            .map_err(|why| format!("Couldn't create {} because: {}",
+                                   dir.display(), why))?;
+        }
+
+        let _lock = FileLock::acquire(path, Duration::from_millis(lock_timeout_ms))?;
+        let changed = change(&self.read()?)?;
+        atomic_write::write(path, changed.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use test_support::TempDir;
+    use super::Store;
+
+    #[test]
+    fn file_store_creates_its_directory_and_replaces_the_file() {
+        let dir = TempDir::new("store");
+        let store = Store::file(&dir.join("trash/notes.json"), 100);
+        assert_eq!(store.read().unwrap(), "");
+
+        store.update(|text| Ok(format!("{}first", text))).unwrap();
+        store.update(|text| Ok(format!("{} second", text))).unwrap();
+        assert_eq!(store.read().unwrap(), "first second");
+        assert_eq!(fs::read_to_string(dir.join("trash/notes.json")).unwrap(), "first second");
+        assert_eq!(store.path(), Some(dir.join("trash/notes.json").as_path()));
+    }
+
+    #[test]
+    fn failed_update_keeps_the_text() {
+        let dir = TempDir::new("store");
+        for store in [Store::memory(), Store::file(&dir.join("log.json"), 100)].iter() {
+            store.update(|_| Ok("kept".to_string())).unwrap();
+            assert!(store.update(|_| Err("failed".to_string())).is_err());
+            assert_eq!(store.read().unwrap(), "kept");
+        }
+    }
+
+    #[test]
+    fn memory_store_clones_share_the_text() {
+        let store = Store::memory();
+        let clone = store.clone();
+
+        clone.update(|_| Ok("shared".to_string())).unwrap();
+        assert_eq!(store.read().unwrap(), "shared");
+    }
+}
@@ -0,0 +1,46 @@
+//! Helpers shared by the unit tests.
+
+use config::Config;
+use entry::{Entry, EntryBuilder};
+use journal::JournalRegistry;
+use memory_journal::MemoryJournalFactory;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+pub fn entry(content: &str) -> Entry {
+    EntryBuilder::new().content(content.to_string()).finalize().unwrap()
+}
+
+// Builtin backends and memory journals
+pub fn registry() -> JournalRegistry {
+    let mut registry = JournalRegistry::with_builtins();
+    registry.register(Box::new(MemoryJournalFactory));
+    registry
+}
+
+// Config without a file, memory journals included
+pub fn memory_config(text: &str) -> Config {
+    Config::from_str_with_registry(text, registry()).unwrap()
+}
+
+// Unique directory in the system temp dir, deleted with everything in it when
+// dropped. It's created on first use by the code under test.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        TempDir(env::temp_dir().join(format!("rjrn-{}-test-{}", name, Uuid::new_v4().simple())))
+    }
+
+    pub fn join(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,246 @@
+//! Trash of a journal. Removed entries are moved here together with the time
+//! they were removed and the journal they came from, so they can be restored
+//! until the trash is emptied. Every journal has its own trash file, next to
+//! the config in `~/.rjrn.trash/<journal>.json`, sealed by the journal so
+//! entries of encrypted journals stay encrypted. Configs without a file keep
+//! it in memory.
+//!
+//!```
+//!    use rjrn::entry::EntryBuilder;
+//!    use rjrn::journal::Journal;
+//!    use rjrn::memory_journal::MemoryJournal;
+//!    use rjrn::trash::{self, Trash};
+//!    use std::fs;
+//!
+//!    let dir = std::env::temp_dir().join("rjrn-trash-doc");
+//!    let journal: Box<dyn Journal> = Box::new(MemoryJournal::new("default"));
+//!    let trash = Trash::new(&dir.join("default.json"), 100, &journal);
+//!
+//!    let mut builder = EntryBuilder::new();
+//!    let entry = builder.content("Removed by mistake".to_string()).finalize().unwrap();
+//!    trash.put("default", vec![Box::new(entry.clone())]).unwrap();
+//!    assert_eq!(trash.entries().unwrap()[0].journal(), "default");
+//!
+//!    assert_eq!(trash.empty(Some(trash::parse_age("30d").unwrap())).unwrap(), 0);
+//!    assert_eq!(trash.remove(&vec![entry.id().clone()]).unwrap(), 1);
+//!    assert!(trash.entries().unwrap().is_empty());
+//!
+//!    fs::remove_dir_all(&dir).unwrap();
+//!```
+
+use chrono::*;
+use serde_json;
+use std::path::Path;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use entry::Entry;
+use journal::Journal;
+use store::Store;
+
+#[derive(Deserialize, Serialize)]
+#[derive(Debug, Clone)]
+pub struct TrashedEntry {
+    entry: Entry,
+    journal: String,
+    deleted_at: String,
+}
+
+impl TrashedEntry {
+    pub fn entry(&self) -> &Entry {
+        &self.entry
+    }
+
+    // Name of the journal the entry was removed from
+    pub fn journal(&self) -> &str {
+        &self.journal
+    }
+
+    pub fn deleted_at(&self) -> DateTime<Utc> {
+        DateTime::<Utc>::from_str(&self.deleted_at).unwrap_or(Utc::now())
+    }
+}
+
+#[derive(Debug)]
+pub struct Trash<'a> {
+    store: Store,
+    // Seals the trash
+    journal: &'a Box<dyn Journal>,
+}
+
+impl<'a> Trash<'a> {
+    pub fn new(path: &Path, lock_timeout_ms: u64, journal: &'a Box<dyn Journal>) -> Trash<'a> {
+        Trash::with_store(Store::file(path, lock_timeout_ms), journal)
+    }
+
+    pub fn with_store(store: Store, journal: &'a Box<dyn Journal>) -> Trash<'a> {
+        Trash { store, journal }
+    }
+
+    // None when the trash is kept in memory
+    pub fn path(&self) -> Option<&Path> {
+        self.store.path()
+    }
+
+    // Oldest removed first
+    pub fn entries(&self) -> Result<Vec<TrashedEntry>, String> {
+        self.parse(&self.store.read()?)
+    }
+
+    pub fn put(&self, journal: &str, entries: Vec<Box<Entry>>) -> Result<(), String> {
+        let now = Utc::now().to_rfc3339();
+        self.store.update(|text| {
+            let mut trashed = self.parse(text)?;
+            for entry in entries.into_iter() {
+                debug!("Moving entry with id: {} to {}", entry.id(), self.store.describe());
+                trashed.push(TrashedEntry {
+                    entry: *entry,
+                    journal: journal.to_string(),
+                    deleted_at: now.clone(),
+                });
+            }
+            self.seal(&trashed)
+        })
+    }
+
+    // Forgets the entries, once they're restored. Returns how many were found.
+    pub fn remove(&self, ids: &Vec<Uuid>) -> Result<usize, String> {
+        self.retain(|t| !ids.contains(t.entry.id()))
+    }
+
+    // Purges the entries removed more than `older_than` ago, or all of them.
+    // Returns how many were purged.
+    pub fn empty(&self, older_than: Option<Duration>) -> Result<usize, String> {
+        match older_than {
+            Some(age) => {
+                let before = Utc::now() - age;
+                self.retain(|t| t.deleted_at() >= before)
+            },
+            None => self.retain(|_| false)
+        }
+    }
+
+    fn retain<F: Fn(&TrashedEntry) -> bool>(&self, keep: F) -> Result<usize, String> {
+        let mut removed = 0;
+        self.store.update(|text| {
+            let trashed = self.parse(text)?;
+            let total = trashed.len();
+            let kept: Vec<TrashedEntry> = trashed.into_iter().filter(|t| keep(t)).collect();
+            removed = total - kept.len();
+            if removed == 0 {
+                return Ok(text.to_string());
+            }
+            self.seal(&kept)
+        })?;
+        Ok(removed)
+    }
+
+    fn parse(&self, text: &str) -> Result<Vec<TrashedEntry>, String> {
+        if text.trim().is_empty() {
+            return Ok(vec![]);
+        }
+
+        serde_json::from_str(&self.journal.unseal(text)?)
+            .map_err(|why| format!("Couldn't read the trash {} because: {}",
+                                   self.store.describe(), why))
+    }
+
+    fn seal(&self, trashed: &Vec<TrashedEntry>) -> Result<String, String> {
+        self.journal.seal(&serde_json::to_string(trashed).unwrap())
+    }
+}
+
+// `30d`, `12h`, `2w` or `45m`
+pub fn parse_age(age: &str) -> Result<Duration, String> {
+    let age = age.trim();
+    let invalid = || format!("Invalid age `{}`, expected a number followed by m, h, d or w, like 30d", age);
+
+    let unit = match age.chars().last() {
+        Some(unit) => unit,
+        None => return Err(invalid())
+    };
+    let number = age[..age.len() - unit.len_utf8()].parse::<i64>().map_err(|_| invalid())?;
+
+    match unit {
+        'm' => Ok(Duration::minutes(number)),
+        'h' => Ok(Duration::hours(number)),
+        'd' => Ok(Duration::days(number)),
+        'w' => Ok(Duration::weeks(number)),
+        _ => Err(invalid())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::*;
+    use entry::Entry;
+    use journal::Journal;
+    use memory_journal::MemoryJournal;
+    use test_support::{self, TempDir};
+    use uuid::Uuid;
+    use super::{Trash, parse_age};
+
+    fn entry(content: &str) -> Box<Entry> {
+        Box::new(test_support::entry(content))
+    }
+
+    #[test]
+    fn put_keeps_the_journal_and_remove_forgets_only_given_ids() {
+        let dir = TempDir::new("trash");
+        let journal: Box<dyn Journal> = Box::new(MemoryJournal::new("work"));
+        let trash = Trash::new(&dir.join("work.json"), 100, &journal);
+        let (first, second) = (entry("First"), entry("Second"));
+
+        trash.put("work", vec![first.clone(), second.clone()]).unwrap();
+        assert_eq!(trash.entries().unwrap().len(), 2);
+        assert!(trash.entries().unwrap().iter().all(|t| t.journal() == "work"));
+
+        assert_eq!(trash.remove(&vec![*first.id(), Uuid::new_v4()]).unwrap(), 1);
+        let left = trash.entries().unwrap();
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].entry().id(), second.id());
+    }
+
+    #[test]
+    fn empty_purges_only_entries_older_than_the_age() {
+        let dir = TempDir::new("trash");
+        let journal: Box<dyn Journal> = Box::new(MemoryJournal::new("work"));
+        let trash = Trash::new(&dir.join("work.json"), 100, &journal);
+        trash.put("work", vec![entry("Just removed")]).unwrap();
+
+        assert_eq!(trash.empty(Some(Duration::days(1))).unwrap(), 0);
+        assert_eq!(trash.entries().unwrap().len(), 1);
+        assert_eq!(trash.empty(Some(Duration::seconds(-1))).unwrap(), 1);
+        assert!(trash.entries().unwrap().is_empty());
+
+        trash.put("work", vec![entry("Removed again")]).unwrap();
+        assert_eq!(trash.empty(None).unwrap(), 1);
+    }
+
+    #[test]
+    fn missing_trash_is_empty() {
+        let dir = TempDir::new("trash");
+        let journal: Box<dyn Journal> = Box::new(MemoryJournal::new("work"));
+        let trash = Trash::new(&dir.join("work.json"), 100, &journal);
+
+        assert!(trash.entries().unwrap().is_empty());
+        assert!(!dir.join("work.json").exists());
+    }
+
+    #[test]
+    fn parse_age_reads_every_unit() {
+        assert_eq!(parse_age("45m").unwrap(), Duration::minutes(45));
+        assert_eq!(parse_age("12h").unwrap(), Duration::hours(12));
+        assert_eq!(parse_age(" 30d ").unwrap(), Duration::days(30));
+        assert_eq!(parse_age("2w").unwrap(), Duration::weeks(2));
+    }
+
+    #[test]
+    fn parse_age_rejects_other_input() {
+        assert!(parse_age("").is_err());
+        assert!(parse_age("30").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("3y").is_err());
+        assert!(parse_age("1.5d").is_err());
+    }
+}
@@ -0,0 +1,500 @@
+//! Trello Journal keeps entries as cards in a Trello list. The title is the
+//! card's name, the content its description, tags are labels and starred
+//! entries get an extra label (`starred` by default).
+//!
+//! Cards have no place for our UUIDs, so the id of an entry is made from the
+//! id of its card. Entries added from rjrn get a new id once the card exists.
+//! Cards keep no earlier versions, so their entries have no revisions.
+//!
+//! The API key, token and base URL are kept in the journal's config, so it
+//! can also talk to a local server:
+//!
+//!```
+//!    use rjrn::journal::Journal;
+//!    use rjrn::trello_journal::TrelloJournal;
+//!    let journal = TrelloJournal::new("name", "key", "token", "list-id",
+//!                                     "http://127.0.0.1:8080/1");
+//!    assert_eq!(journal, TrelloJournal::from_json(&journal.to_json()).unwrap());
+//!```
+
+use serde_json::{self, Map, Value};
+use chrono::*;
+use std::env;
+use std::str::FromStr;
+use ureq;
+use uuid::Uuid;
+
+use journal::{config_string, Journal, JournalFactory, JournalOptions, JournalRegistry};
+use entry::{Entry, EntryBuilder};
+use prompt;
+
+pub const DEFAULT_BASE_URL: &str = "https://api.trello.com/1";
+// Where `rjrn journal add --type trello` looks for the key and token
+pub const KEY_ENV: &str = "RJRN_TRELLO_KEY";
+pub const TOKEN_ENV: &str = "RJRN_TRELLO_TOKEN";
+const DEFAULT_STAR_LABEL: &str = "starred";
+const STAR_LABEL_COLOR: &str = "yellow";
+// Last bytes of ids made from Trello cards, cards have 12 byte ids
+const CARD_ID_MARKER: [u8; 4] = [0x74, 0x72, 0x65, 0x6c];
+
+#[derive(Debug)]
+pub struct TrelloJournal {
+    name: String,
+    key: String,
+    token: String,
+    list_id: String,
+    base_url: String,
+    star_label: String,
+    default: bool,
+}
+
+#[derive(Debug)]
+struct Label {
+    id: String,
+    name: String,
+}
+
+impl Journal for TrelloJournal {
+    fn to_json(&self) -> Value {
+        let mut d = Map::new();
+        d.insert("name".to_string(), Value::String(self.name.clone()));
+        d.insert("key".to_string(), Value::String(self.key.clone()));
+        d.insert("token".to_string(), Value::String(self.token.clone()));
+        d.insert("list_id".to_string(), Value::String(self.list_id.clone()));
+        d.insert("base_url".to_string(), Value::String(self.base_url.clone()));
+        d.insert("star_label".to_string(), Value::String(self.star_label.clone()));
+        d.insert("type".to_string(), Value::String("TrelloJournal".to_string()));
+        d.insert("default".to_string(), Value::Bool(self.default));
+        Value::Object(d)
+    }
+
+    fn set_default(&mut self) {
+        self.default = true
+    }
+
+    fn is_default(&self) -> bool {
+        self.default
+    }
+
+    fn name(&self) -> &String {
+        &self.name
+    }
+
+    fn entries(&self) -> Result<Vec<Box<Entry>>, String> {
+        let cards = self.request("GET", &format!("/lists/{}/cards", self.list_id),
+                                 &[("fields", "id,name,desc,dateLastActivity,labels")])?;
+
+        let mut entries = vec![];
+        for card in as_array(&cards)?.iter() {
+            match self.to_entry(card) {
+                Ok(entry) => entries.push(Box::new(entry)),
+                Err(why) => warn!("Skipping Trello card: {}", why)
+            }
+        }
+
+        Ok(entries)
+    }
+
+    // New cards get an id made from the card's id
+    fn upsert_entry(&self, entry: Entry) -> Result<Uuid, String> {
+        debug!("Add entry with title:: {:?} to Trello list {}", entry.title(), self.list_id);
+        let label_ids = self.label_ids(&entry)?.join(",");
+        let name = card_name(&entry);
+        let form = [("name", name.as_str()),
+                    ("desc", entry.content().as_str()),
+                    ("idLabels", label_ids.as_str())];
+
+        match card_id(entry.id()) {
+            Some(card_id) => {
+                self.request("PUT", &format!("/cards/{}", card_id), &form)?;
+                Ok(*entry.id())
+            },
+            None => {
+                let mut form = form.to_vec();
+                form.push(("idList", self.list_id.as_str()));
+                form.push(("pos", "bottom"));
+                let card = self.request("POST", "/cards", &form)?;
+                entry_id(&string_field(&card, "id")?)
+            }
+        }
+    }
+
+    fn remove_entries(&self, ids: &Vec<Uuid>) -> Result<(), String> {
+        for id in ids.iter() {
+            match card_id(id) {
+                Some(card_id) => { self.request("DELETE", &format!("/cards/{}", card_id), &[])?; },
+                None => warn!("Entry {} isn't a Trello card", id)
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl TrelloJournal {
+    pub fn new(name: &str, key: &str, token: &str, list_id: &str, base_url: &str) -> TrelloJournal {
+        TrelloJournal {
+            name: name.to_string(),
+            key: key.to_string(),
+            token: token.to_string(),
+            list_id: list_id.to_string(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            star_label: DEFAULT_STAR_LABEL.to_string(),
+            default: false,
+        }
+    }
+
+    pub fn from_json(j: &Value) -> Result<TrelloJournal, String> {
+        let optional = |key: &str, default: &str| {
+            j.get(key).and_then(|v| v.as_str()).unwrap_or(default).to_string()
+        };
+
+        Ok(TrelloJournal {
+            name: config_string(j, "name")?,
+            key: config_string(j, "key")?,
+            token: config_string(j, "token")?,
+            list_id: config_string(j, "list_id")?,
+            base_url: optional("base_url", DEFAULT_BASE_URL),
+            star_label: optional("star_label", DEFAULT_STAR_LABEL),
+            default: j.get("default").and_then(|d| d.as_bool()).unwrap_or(false),
+        })
+    }
+
+    // Used by the CLI when the user want to create a new journal of that type
+    pub fn bootstrap_cli() -> Result<TrelloJournal, String> {
+        let name = prompt::journal_name();
+        let key = prompt::read_line("Trello API key (https://trello.com/app-key):");
+        let token = prompt::read_line("Trello API token:");
+        let list_id = prompt::read_line("Id of the Trello list for entries:");
+        let base_url = prompt::read_line(&format!("Trello API URL ({}):", DEFAULT_BASE_URL));
+
+        if key.is_empty() || token.is_empty() || list_id.is_empty() {
+            return Err("Trello journal needs an API key, token and list id".to_string());
+        }
+
+        let base_url = if base_url.is_empty() { DEFAULT_BASE_URL.to_string() } else { base_url };
+        Ok(TrelloJournal::new(&name, &key, &token, &list_id, &base_url))
+    }
+
+    fn request(&self, method: &str, path: &str, params: &[(&str, &str)]) -> Result<Value, String> {
+        let url = format!("{}{}", self.base_url, path);
+        debug!("Trello request: {} {}", method, url);
+
+        let request = ureq::request(method, &url)
+            .query("key", &self.key)
+            .query("token", &self.token);
+
+        let response = if method == "GET" || method == "DELETE" {
+            params.iter()
+                .fold(request, |request, &(name, value)| request.query(name, value))
+                .call()
+        } else {
+            request.send_form(params)
+        };
+
+        let body = response
+                   .map_err(|why| format!("Trello request {} {} failed: {}", method, path, why))
+                   .and_then(|response| response.into_string()
+                             .map_err(|why| format!("Couldn't read Trello response: {}", why)))?;
+
+        if body.trim().is_empty() {
+            return Ok(Value::Null);
+        }
+
+        serde_json::from_str::<Value>(&body)
+            .map_err(|why| format!("Couldn't parse Trello response: {}", why))
+    }
+
+    fn board_id(&self) -> Result<String, String> {
+        let list = self.request("GET", &format!("/lists/{}", self.list_id),
+                                &[("fields", "idBoard")])?;
+        string_field(&list, "idBoard")
+    }
+
+    fn board_labels(&self, board_id: &str) -> Result<Vec<Label>, String> {
+        let labels = self.request("GET", &format!("/boards/{}/labels", board_id),
+                                  &[("fields", "id,name")])?;
+        as_array(&labels)?.iter().map(to_label).collect()
+    }
+
+    // Labels for the tags and the star, creating the ones missing on the board.
+    // Every tag is labelled once, tags named like the star label are left out
+    // as that label stands for the star.
+    fn label_ids(&self, entry: &Entry) -> Result<Vec<String>, String> {
+        let mut names: Vec<(&str, Option<&str>)> = vec![];
+        for tag in entry.tags().iter() {
+            if *tag != self.star_label && !names.iter().any(|&(name, _)| name == tag.as_str()) {
+                names.push((tag.as_str(), None));
+            }
+        }
+        if entry.starred() {
+            names.push((self.star_label.as_str(), Some(STAR_LABEL_COLOR)));
+        }
+        if names.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let board_id = self.board_id()?;
+        let mut labels = self.board_labels(&board_id)?;
+        let mut ids = vec![];
+
+        for (name, color) in names.into_iter() {
+            if let Some(label) = labels.iter().find(|l| l.name == name) {
+                ids.push(label.id.clone());
+                continue;
+            }
+
+            // Labels without a colour are left without one
+            let mut form = vec![("name", name)];
+            if let Some(color) = color {
+                form.push(("color", color));
+            }
+            let created = self.request("POST", &format!("/boards/{}/labels", board_id), &form)?;
+            let label = Label { id: string_field(&created, "id")?, name: name.to_string() };
+            ids.push(label.id.clone());
+            labels.push(label);
+        }
+
+        Ok(ids)
+    }
+
+    fn to_entry(&self, card: &Value) -> Result<Entry, String> {
+        let card_id = string_field(card, "id")?;
+        let name = string_field(card, "name")?;
+        let desc = card.get("desc").and_then(|d| d.as_str()).unwrap_or("").to_string();
+        let labels: Vec<Label> = match card.get("labels") {
+            Some(labels) => as_array(labels)?.iter().map(to_label).collect::<Result<_, _>>()?,
+            None => vec![]
+        };
+
+        let created_at = card_created_at(&card_id)?;
+        let updated_at = card.get("dateLastActivity")
+            .and_then(|d| d.as_str())
+            .and_then(|d| DateTime::<Utc>::from_str(d).ok())
+            .unwrap_or(created_at);
+
+        let mut builder = EntryBuilder::new();
+        builder.id(entry_id(&card_id)?)
+            .title(name.clone())
+            .raw_content(if desc.is_empty() { name } else { desc })
+            .created_at(created_at)
+            .updated_at(updated_at)
+            .starred(labels.iter().any(|l| l.name == self.star_label))
+            .tags(labels.into_iter()
+                  .map(|l| l.name)
+                  .filter(|name| !name.is_empty() && *name != self.star_label)
+                  .collect());
+
+        builder.finalize()
+    }
+}
+
+fn card_name(entry: &Entry) -> String {
+    match *entry.title() {
+        Some(ref title) if !title.trim().is_empty() => title.clone(),
+        _ => entry.content().lines().next().unwrap_or("").to_string()
+    }
+}
+
+// Card ids are 24 hex digits, the first 8 are the time the card was created
+fn entry_id(card_id: &str) -> Result<Uuid, String> {
+    if card_id.len() != 24 || !card_id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Unexpected Trello card id: {}", card_id));
+    }
+
+    let mut bytes = [0u8; 16];
+    for i in 0..12 {
+        bytes[i] = u8::from_str_radix(&card_id[i * 2..i * 2 + 2], 16).unwrap();
+    }
+    bytes[12..].copy_from_slice(&CARD_ID_MARKER);
+
+    Ok(Uuid::from_bytes(bytes))
+}
+
+fn card_id(id: &Uuid) -> Option<String> {
+    let bytes = id.as_bytes();
+    if bytes[12..] != CARD_ID_MARKER {
+        return None;
+    }
+
+    Some(bytes[..12].iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn card_created_at(card_id: &str) -> Result<DateTime<Utc>, String> {
+    i64::from_str_radix(&card_id[..8], 16).ok()
+        .and_then(|seconds| Utc.timestamp_opt(seconds, 0).single())
+        .ok_or(format!("Unexpected Trello card id: {}", card_id))
+}
+
+fn to_label(label: &Value) -> Result<Label, String> {
+    Ok(Label {
+        id: string_field(label, "id")?,
+        name: label.get("name").and_then(|n| n.as_str()).unwrap_or("").to_string(),
+    })
+}
+
+fn string_field(json: &Value, key: &str) -> Result<String, String> {
+    json.get(key)
+        .and_then(|v| v.as_str())
+        .map(|v| v.to_string())
+        .ok_or(format!("Trello response is missing `{}`", key))
+}
+
+fn as_array(json: &Value) -> Result<&Vec<Value>, String> {
+    json.as_array().ok_or("Expected a list in the Trello response".to_string())
+}
+
+// Secrets given as options end up in the shell history, they can come from
+// the environment instead
+fn secret(options: &JournalOptions, key: &str, env_var: &str) -> Result<String, String> {
+    if let Some(value) = options.get(key) {
+        return Ok(value.clone());
+    }
+
+    env::var(env_var)
+        .ok()
+        .filter(|value| !value.is_empty())
+        .ok_or(format!("Please give {} of the journal with ${} or --option {}=- to read it from stdin",
+                       key, env_var, key))
+}
+
+pub struct TrelloJournalFactory;
+
+impl JournalFactory for TrelloJournalFactory {
+    fn kind(&self) -> &'static str {
+        "TrelloJournal"
+    }
+
+    fn description(&self) -> &'static str {
+        "Trello Journal"
+    }
+
+    fn bootstrap_cli(&self, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        Ok(Box::new(TrelloJournal::bootstrap_cli()?))
+    }
+
+    fn from_json(&self, journal: &Value, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        Ok(Box::new(TrelloJournal::from_json(journal)?))
+    }
+
+    fn from_options(&self, options: &JournalOptions, _registry: &JournalRegistry) -> Result<Box<dyn Journal>, String> {
+        let base_url = options.get("base_url").map(|u| u.as_str()).unwrap_or(DEFAULT_BASE_URL);
+        Ok(Box::new(TrelloJournal::new(&options.name, &secret(options, "key", KEY_ENV)?,
+                                       &secret(options, "token", TOKEN_ENV)?,
+                                       options.require("list_id")?, base_url)))
+    }
+}
+
+impl PartialEq for TrelloJournal {
+     fn eq(&self, other: &TrelloJournal) -> bool {
+         self.base_url == other.base_url && self.list_id == other.list_id
+     }
+ }
+
+#[cfg(test)]
+mod tests {
+    use entry::EntryBuilder;
+    use journal::{Journal, JournalFactory, JournalOptions, JournalRegistry};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+    use test_support::entry;
+    use super::{card_id, entry_id, TrelloJournal, TrelloJournalFactory};
+
+    const CARD_ID: &str = "65a1b2c3d4e5f60718293a4b";
+
+    // Answers one request per body, in order, and returns the requests it got
+    // as `METHOD /path form`
+    fn serve(bodies: Vec<&'static str>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/1", listener.local_addr().unwrap());
+
+        let server = thread::spawn(move || bodies.into_iter().map(|body| {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let mut length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() { break; }
+                if let Some(value) = header.to_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut form = vec![0u8; length];
+            reader.read_exact(&mut form).unwrap();
+
+            write!(reader.get_mut(), "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                   body.len(), body).unwrap();
+            let path = line.split(' ').nth(1).unwrap().split('?').next().unwrap().to_string();
+            format!("{} {} {}", line.split(' ').next().unwrap(), path, String::from_utf8(form).unwrap())
+                .trim_end().to_string()
+        }).collect());
+
+        (url, server)
+    }
+
+    #[test]
+    fn card_ids_make_stable_entry_ids() {
+        let id = entry_id(CARD_ID).unwrap();
+        assert_eq!(card_id(&id), Some(CARD_ID.to_string()));
+        assert_eq!(card_id(entry("Deploy").id()), None);
+        assert!(entry_id("not-a-card").is_err());
+    }
+
+    #[test]
+    fn cards_are_read_from_the_configured_server() {
+        let (url, server) = serve(vec![r#"[{"id": "65a1b2c3d4e5f60718293a4b", "name": "Deploy", "desc": "",
+                                            "dateLastActivity": "2026-01-13T10:00:00.000Z",
+                                            "labels": [{"id": "l1", "name": "work"}, {"id": "l2", "name": "starred"}]}]"#]);
+        let journal = TrelloJournal::new("cards", "key", "token", "list", &url);
+
+        let entries = journal.entries().unwrap();
+        assert_eq!(server.join().unwrap(), ["GET /1/lists/list/cards"]);
+        assert_eq!(entries[0].id(), &entry_id(CARD_ID).unwrap());
+        assert_eq!(entries[0].title(), &Some("Deploy".to_string()));
+        assert_eq!(entries[0].content(), "Deploy");
+        assert_eq!(entries[0].tags(), &vec!["work"]);
+        assert!(entries[0].starred());
+        assert_eq!(entries[0].created_at().to_rfc3339(), "2024-01-12T21:44:35+00:00");
+    }
+
+    #[test]
+    fn new_entries_become_cards_with_labels() {
+        let (url, server) = serve(vec![r#"{"idBoard": "board"}"#,
+                                       r#"[{"id": "l1", "name": "work"}]"#,
+                                       r#"{"id": "l2"}"#,
+                                       r#"{"id": "65a1b2c3d4e5f60718293a4b"}"#,
+                                       ""]);
+        let journal = TrelloJournal::new("cards", "key", "token", "list", &url);
+        let mut builder = EntryBuilder::new();
+        builder.content("Deploy. Went fine".to_string()).tag("work").starred(true);
+
+        let id = journal.upsert_entry(builder.finalize().unwrap()).unwrap();
+        assert_eq!(id, entry_id(CARD_ID).unwrap());
+        journal.remove_entries(&vec![id]).unwrap();
+
+        assert_eq!(server.join().unwrap(), [
+            "GET /1/lists/list",
+            "GET /1/boards/board/labels",
+            "POST /1/boards/board/labels name=starred&color=yellow",
+            "POST /1/cards name=Deploy&desc=Deploy.+Went+fine&idLabels=l1%2Cl2&idList=list&pos=bottom",
+            &format!("DELETE /1/cards/{}", CARD_ID),
+        ]);
+    }
+
+    #[test]
+    fn options_give_the_list_and_the_secrets() {
+        let mut options = JournalOptions::new("cards");
+        options.settings.insert("key".to_string(), "key".to_string());
+        options.settings.insert("token".to_string(), "token".to_string());
+        assert!(TrelloJournalFactory.from_options(&options, &JournalRegistry::new()).is_err());
+
+        options.settings.insert("list_id".to_string(), "list".to_string());
+        options.settings.insert("base_url".to_string(), "http://127.0.0.1:8080/1/".to_string());
+        let journal = TrelloJournalFactory.from_options(&options, &JournalRegistry::new()).unwrap();
+        assert_eq!(journal.to_json()["base_url"], "http://127.0.0.1:8080/1");
+    }
+}